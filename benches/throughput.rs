@@ -0,0 +1,136 @@
+use batpu_assembly::components::address::Address;
+use batpu_assembly::components::condition::Condition;
+use batpu_assembly::components::immediate::Immediate;
+use batpu_assembly::components::location::Location;
+use batpu_assembly::components::offset::Offset;
+use batpu_assembly::components::register::Register;
+use batpu_assembly::instruction::Instruction;
+use batpu_assembly::InstructionVec;
+use batpu_emulator::machine::run::StopReason;
+use batpu_emulator::machine::Machine;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const CYCLES: u64 = 20_000_000;
+const ROUNDS: usize = 5;
+
+fn main() {
+    let workloads: [(&str, InstructionVec); 3] = [
+        ("arithmetic", arithmetic()),
+        ("memory and calls", memory_and_calls()),
+        ("screen ports", screen_ports())
+    ];
+
+    println!("{:<20}{:>14}{:>14}{:>14}{:>14}{:>10}", "workload", "decoded", "cycles/s", "cloned", "cycles/s", "speedup");
+
+    for (name, program) in workloads {
+        let decoded = best(&program, run_decoded);
+        let cloned = best(&program, run_cloned);
+
+        println!(
+            "{:<20}{:>11.2} ms{:>12.2} M{:>11.2} ms{:>12.2} M{:>9.2}x",
+            name,
+            decoded.as_secs_f64() * 1000.0,
+            rate(decoded),
+            cloned.as_secs_f64() * 1000.0,
+            rate(cloned),
+            cloned.as_secs_f64() / decoded.as_secs_f64()
+        );
+    }
+}
+
+fn best(program: &InstructionVec, run: fn(&mut Machine)) -> Duration {
+    (0..ROUNDS)
+        .map(|_| measure(program, run))
+        .min()
+        .unwrap()
+}
+
+fn rate(duration: Duration) -> f64 {
+    CYCLES as f64 / duration.as_secs_f64() / 1_000_000.0
+}
+
+fn measure(program: &InstructionVec, run: fn(&mut Machine)) -> Duration {
+    let mut machine = Machine::new();
+    machine.set_instructions(program.clone());
+
+    let start = Instant::now();
+    run(&mut machine);
+    let elapsed = start.elapsed();
+
+    assert_eq!(machine.cycles(), CYCLES, "benchmark program stopped early");
+    black_box(machine.registers());
+
+    elapsed
+}
+
+fn run_decoded(machine: &mut Machine) {
+    let result = machine.run_for(CYCLES);
+    assert_eq!(result.reason, StopReason::CyclesExhausted, "benchmark program stopped early");
+}
+
+fn run_cloned(machine: &mut Machine) {
+    for _ in 0..CYCLES {
+        machine.tick_uncached().expect("benchmark program faulted");
+    }
+}
+
+fn reg(register: u32) -> Register {
+    Register::new(register as _)
+}
+
+fn imm(immediate: u32) -> Immediate {
+    Immediate::new(immediate as _)
+}
+
+fn off(offset: i32) -> Offset {
+    Offset::new(offset as _)
+}
+
+fn to(address: u32) -> Location {
+    Location::Address(Address::new(address as _))
+}
+
+fn arithmetic() -> InstructionVec {
+    vec![
+        Instruction::LoadImmediate(reg(1), imm(0)),
+        Instruction::LoadImmediate(reg(2), imm(3)),
+        Instruction::AddImmediate(reg(1), imm(1)),
+        Instruction::Addition(reg(1), reg(2), reg(3)),
+        Instruction::Subtraction(reg(3), reg(1), reg(4)),
+        Instruction::BitwiseXOR(reg(3), reg(4), reg(5)),
+        Instruction::BitwiseAND(reg(5), reg(1), reg(6)),
+        Instruction::BitwiseNOR(reg(6), reg(2), reg(7)),
+        Instruction::RightShift(reg(7), reg(8)),
+        Instruction::Branch(Condition::NotZero, to(2)),
+        Instruction::Jump(to(0))
+    ]
+}
+
+fn memory_and_calls() -> InstructionVec {
+    vec![
+        Instruction::LoadImmediate(reg(1), imm(0)),
+        Instruction::Call(to(4)),
+        Instruction::AddImmediate(reg(1), imm(1)),
+        Instruction::Jump(to(1)),
+        Instruction::MemoryStore(reg(1), reg(1), off(0)),
+        Instruction::MemoryLoad(reg(1), reg(2), off(1)),
+        Instruction::AddImmediate(reg(2), imm(7)),
+        Instruction::MemoryStore(reg(1), reg(2), off(-1)),
+        Instruction::Return
+    ]
+}
+
+fn screen_ports() -> InstructionVec {
+    vec![
+        Instruction::LoadImmediate(reg(1), imm(240)),
+        Instruction::LoadImmediate(reg(2), imm(0)),
+        Instruction::MemoryStore(reg(1), reg(2), off(0)),
+        Instruction::MemoryStore(reg(1), reg(2), off(1)),
+        Instruction::MemoryStore(reg(1), reg(0), off(2)),
+        Instruction::MemoryLoad(reg(1), reg(3), off(4)),
+        Instruction::AddImmediate(reg(2), imm(1)),
+        Instruction::MemoryStore(reg(1), reg(0), off(5)),
+        Instruction::Jump(to(2))
+    ]
+}
@@ -0,0 +1,565 @@
+use batpu_assembly::components::address;
+use batpu_assembly::instruction::Instruction;
+use batpu_emulator::debugger::expression::Expression;
+use batpu_emulator::debugger::{Breakpoint, Watchpoint};
+use batpu_emulator::disassembler::format_instruction;
+use batpu_emulator::machine::run::{RunResult, StopReason};
+use batpu_emulator::machine::{Machine, StepOutcome, MEMORY_SIZE, PORTS, PORTS_ADDRESS, REGISTER_COUNT, USABLE_MEMORY_SIZE};
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{env, io, process};
+
+const RUN_LIMIT: u64 = 100_000_000;
+
+const HELP: &str = "\
+Commands:
+  step [n]                      Execute n instructions (default 1)
+  next                          Step, running over CAL instructions
+  finish                        Run until the current call returns
+  continue                      Run until halt, breakpoint or watchpoint
+                                  runs also stop at a cycle limit or on Ctrl-C
+  break [location [if <expr>]]  Set a breakpoint, or list breakpoints
+                                  location is an address, a label or file:line
+  delete <location>             Remove a breakpoint
+  watch [reg|mem|port] ...      Set a watchpoint, or list watchpoints
+                                  watch r<n>
+                                  watch mem <address> [read|write]
+                                  watch port <port> [read|write]
+  unwatch ...                   Remove a watchpoint, same syntax as watch
+  regs                          Show registers and flags
+  mem [address] [count]         Show memory
+  stack                         Show the call stack
+  screen                        Show the screen and displays
+  disasm [address] [count]      Disassemble the program
+  set <target> <value>          Set r<n>, mem <address>, pc, zero or carry
+  reset                         Reset the machine
+  reload [file]                 Reload the program, keeping the machine state
+  help                          Show this help
+  quit                          Exit";
+
+fn main() {
+    let arguments: Vec<String> = env::args().collect();
+    if arguments.len() != 2 {
+        eprintln!("Usage: {} <program>", arguments[0]);
+        process::exit(2);
+    }
+
+    let mut machine = Machine::new();
+    match machine.load_file(&arguments[1]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+        },
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+
+            process::exit(1);
+        }
+    }
+
+    println!("Loaded {} instructions from {}", machine.instructions().len(), arguments[1]);
+    print_location(&machine);
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if let Err(error) = signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&interrupted)) {
+        eprintln!("Cannot handle Ctrl-C, runs can only be stopped by the cycle limit: {}", error);
+    }
+
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+
+    loop {
+        print!("(batpu) ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        let line = line.trim();
+        let line = if line.is_empty() {
+            last_command.clone()
+        } else {
+            last_command = line.to_string();
+            line.to_string()
+        };
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        if let Err(error) = run_command(&mut machine, &arguments[1], &words, &line, &interrupted) {
+            println!("{}", error);
+        }
+
+        if matches!(words[0], "quit" | "q" | "exit") {
+            break;
+        }
+    }
+}
+
+fn run_command(machine: &mut Machine, program: &str, words: &[&str], line: &str, interrupted: &AtomicBool) -> Result<(), String> {
+    match words[0] {
+        "step" | "s" => {
+            let count = match words.get(1) {
+                Some(count) => parse_count(count)? as u64,
+                None => 1
+            };
+
+            let result = machine_step(machine, count, interrupted);
+            report(machine, result, interrupted);
+        },
+        "next" | "n" => {
+            let program_counter = machine.program_counter();
+            let depth = machine.stack().stack().len();
+
+            let call = matches!(machine.instructions().get(program_counter as usize), Some(Instruction::Call(_)));
+
+            let result = if call {
+                let return_address = program_counter + 1;
+                run(machine, interrupted, |machine| {
+                    machine.program_counter() == return_address && machine.stack().stack().len() <= depth
+                })
+            } else {
+                machine_step(machine, 1, interrupted)
+            };
+
+            report(machine, result, interrupted);
+        },
+        "finish" | "f" => {
+            let depth = machine.stack().stack().len();
+            if depth == 0 {
+                return Err("Not inside a call".to_string());
+            }
+
+            let result = run(machine, interrupted, |machine| machine.stack().stack().len() < depth);
+            report(machine, result, interrupted);
+        },
+        "continue" | "c" => {
+            let result = run(machine, interrupted, |_| false);
+            report(machine, result, interrupted);
+        },
+        "break" | "b" => {
+            if words.len() == 1 {
+                for (address, breakpoint) in machine.debugger().breakpoints() {
+                    match &breakpoint.condition {
+                        Some(condition) => println!("  {:>4} if {} (hit {} times)", address, condition, breakpoint.hit_count()),
+                        None => println!("  {:>4} (hit {} times)", address, breakpoint.hit_count())
+                    }
+                }
+
+                return Ok(());
+            }
+
+            let address = parse_location(machine, words[1])?;
+            let breakpoint = match line.find(" if ") {
+                Some(index) => {
+                    let condition = Expression::parse(&line[index + 4..]).map_err(|error| error.to_string())?;
+                    Breakpoint::with_condition(condition)
+                },
+                None => Breakpoint::new()
+            };
+
+            machine.debugger_mut().set_breakpoint(address, breakpoint);
+            println!("Breakpoint set at {}", machine.describe(address));
+        },
+        "delete" | "d" => {
+            let address = parse_location(machine, words.get(1).ok_or("Expected a location")?)?;
+            match machine.debugger_mut().remove_breakpoint(address) {
+                Some(_) => println!("Breakpoint at {} removed", address),
+                None => println!("No breakpoint at {}", address)
+            }
+        },
+        "watch" | "w" => {
+            if words.len() == 1 {
+                for watchpoint in machine.debugger().watchpoints() {
+                    println!("  {}", format_watchpoint(watchpoint));
+                }
+
+                return Ok(());
+            }
+
+            let watchpoint = parse_watchpoint(&words[1..])?;
+            machine.debugger_mut().add_watchpoint(watchpoint);
+            println!("Watching {}", format_watchpoint(&watchpoint));
+        },
+        "unwatch" => {
+            let watchpoint = parse_watchpoint(&words[1..])?;
+            if machine.debugger_mut().remove_watchpoint(watchpoint) {
+                println!("Removed {}", format_watchpoint(&watchpoint));
+            } else {
+                println!("Not watching {}", format_watchpoint(&watchpoint));
+            }
+        },
+        "regs" | "r" => print_registers(machine),
+        "mem" | "m" => {
+            let start = match words.get(1) {
+                Some(start) => parse_index(start, "Address", USABLE_MEMORY_SIZE)?,
+                None => 0
+            };
+
+            let count = match words.get(2) {
+                Some(count) => parse_count(count)?,
+                None => USABLE_MEMORY_SIZE - start
+            };
+
+            print_memory(machine, start, count);
+        },
+        "stack" => {
+            let stack = machine.stack().stack();
+            if stack.is_empty() {
+                println!("Stack is empty");
+            }
+
+            for (depth, address) in stack.iter().rev().enumerate() {
+                println!("  #{} {:>4}", depth, address);
+            }
+
+            println!("Depth {} of {}, high water mark {}", stack.len(), machine.stack().max_size(), machine.stack().high_water_mark());
+        },
+        "screen" => print_screen(machine),
+        "disasm" | "x" => {
+            let start = match words.get(1) {
+                Some(start) => parse_count(start)?,
+                None => (machine.program_counter() as usize).saturating_sub(4)
+            };
+
+            let count = match words.get(2) {
+                Some(count) => parse_count(count)?,
+                None => 16
+            };
+
+            print_disassembly(machine, start, count);
+        },
+        "set" => {
+            let target = words.get(1).ok_or("Expected a target")?;
+            match *target {
+                "mem" => {
+                    let address = parse_index(words.get(2).ok_or("Expected an address")?, "Address", USABLE_MEMORY_SIZE)?;
+                    let value = parse_number(words.get(3).ok_or("Expected a value")?)?;
+
+                    machine.memory_mut()[address] = value as u8;
+                },
+                _ => {
+                    let value = parse_number(words.get(2).ok_or("Expected a value")?)?;
+                    set_target(machine, target, value)?;
+                }
+            }
+        },
+        "reset" => {
+            machine.reset();
+            print_location(machine);
+        },
+        "reload" => {
+            let path = words.get(1).copied().unwrap_or(program);
+            let report = machine.reload_file(path).map_err(|diagnostics| {
+                diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect::<Vec<String>>().join("\n")
+            })?;
+
+            println!("Reloaded {} instructions from {}", machine.instructions().len(), path);
+            for warning in &report.warnings {
+                println!("  {}", warning);
+            }
+
+            for remap in report.inexact() {
+                println!("  warning: {}", remap);
+            }
+
+            print_location(machine);
+        },
+        "help" | "h" | "?" => println!("{}", HELP),
+        "quit" | "q" | "exit" => {},
+        command => return Err(format!("Unknown command \"{}\", try \"help\"", command))
+    }
+
+    Ok(())
+}
+
+fn run(machine: &mut Machine, interrupted: &AtomicBool, mut predicate: impl FnMut(&Machine) -> bool) -> RunResult {
+    interrupted.store(false, Ordering::Relaxed);
+    machine.run_until(RUN_LIMIT, |machine| interrupted.load(Ordering::Relaxed) || predicate(machine))
+}
+
+fn machine_step(machine: &mut Machine, count: u64, interrupted: &AtomicBool) -> RunResult {
+    let start = machine.cycles();
+    interrupted.store(false, Ordering::Relaxed);
+
+    if machine.halt() {
+        return RunResult {
+            reason: StopReason::Halted,
+            cycles: 0
+        };
+    }
+
+    loop {
+        let cycles = machine.cycles() - start;
+        if cycles >= count {
+            return RunResult {
+                reason: StopReason::CyclesExhausted,
+                cycles
+            };
+        }
+
+        if interrupted.load(Ordering::Relaxed) {
+            return RunResult {
+                reason: StopReason::Condition,
+                cycles
+            };
+        }
+
+        let outcome = match machine.tick() {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                return RunResult {
+                    reason: StopReason::Fault(error),
+                    cycles
+                };
+            }
+        };
+
+        let reason = match outcome {
+            StepOutcome::Executed | StepOutcome::FramePushed => continue,
+            StepOutcome::Halted => StopReason::Halted,
+            StepOutcome::Breakpoint(address) => {
+                if cycles == 0 {
+                    continue;
+                }
+
+                StopReason::Breakpoint(address)
+            },
+            StepOutcome::Watchpoint { hits, frame_pushed } => StopReason::Watchpoint {
+                hits,
+                frame_pushed
+            }
+        };
+
+        return RunResult {
+            reason,
+            cycles: machine.cycles() - start
+        };
+    }
+}
+
+fn report(machine: &Machine, result: RunResult, interrupted: &AtomicBool) {
+    match result.reason {
+        StopReason::Halted => println!("Halted after {} cycles", result.cycles),
+        StopReason::CyclesExhausted if result.cycles >= RUN_LIMIT => println!("Stopped at the {} cycle limit", RUN_LIMIT),
+        StopReason::Condition if interrupted.swap(false, Ordering::Relaxed) => println!("Interrupted after {} cycles", result.cycles),
+        StopReason::CyclesExhausted | StopReason::Condition => {},
+        StopReason::Breakpoint(address) => println!("Breakpoint at {} after {} cycles", machine.describe(address), result.cycles),
+        StopReason::Watchpoint { hits, frame_pushed } => {
+            for hit in hits {
+                match hit.old {
+                    Some(old) => println!("{} at {}: {} -> {}", format_watchpoint(&hit.watchpoint), hit.address, old, hit.new),
+                    None => println!("{} at {}: {}", format_watchpoint(&hit.watchpoint), hit.address, hit.new)
+                }
+            }
+
+            if frame_pushed {
+                println!("Frame pushed after {} cycles", result.cycles);
+            }
+        },
+        StopReason::FramePushed => println!("Frame pushed after {} cycles", result.cycles),
+        StopReason::Fault(error) => println!("Fault: {}", error)
+    }
+
+    print_location(machine);
+}
+
+fn print_location(machine: &Machine) {
+    let program_counter = machine.program_counter();
+    match machine.instructions().get(program_counter as usize) {
+        Some(instruction) => println!("{:>4}  {:<20}{}", program_counter, format_instruction(instruction), source_comment(machine, program_counter)),
+        None => println!("{:>4}  <end of program>", program_counter)
+    }
+}
+
+fn source_comment(machine: &Machine, address: u32) -> String {
+    match machine.source_map() {
+        Some(_) => format!("  ; {}", machine.describe(address)),
+        None => String::new()
+    }
+}
+
+fn print_registers(machine: &Machine) {
+    for (register, value) in machine.registers().iter().enumerate() {
+        print!("r{:<2} {:>3} ({:02x})", register, value, value);
+
+        if register % 4 == 3 {
+            println!();
+        } else {
+            print!("   ");
+        }
+    }
+
+    println!(
+        "pc {}  zero {}  carry {}  halt {}",
+        machine.program_counter(),
+        machine.zero_flag() as u8,
+        machine.carry_flag() as u8,
+        machine.halt() as u8
+    );
+}
+
+fn print_memory(machine: &Machine, start: usize, count: usize) {
+    let memory = machine.memory();
+    let end = start.saturating_add(count).min(memory.len());
+
+    for row in (start..end).step_by(16) {
+        print!("{:>3}:", row);
+
+        for value in &memory[row..(row + 16).min(end)] {
+            print!(" {:02x}", value);
+        }
+
+        println!();
+    }
+}
+
+fn print_screen(machine: &Machine) {
+    let screen = machine.screen();
+
+    for y in (0..screen.height() as isize).rev() {
+        let row: String = (0..screen.width() as isize)
+            .map(|x| if screen.pixel(x, y) { '#' } else { '.' })
+            .collect();
+
+        println!("{}", row);
+    }
+
+    println!("Characters: \"{}\"", machine.character_display().data());
+    println!("Number: {}", machine.number_display().value());
+}
+
+fn print_disassembly(machine: &Machine, start: usize, count: usize) {
+    for (address, instruction) in machine.instructions().iter().enumerate().skip(start).take(count) {
+        let marker = if address as u32 == machine.program_counter() { '>' } else { ' ' };
+        let breakpoint = if machine.debugger().breakpoint(address as u32).is_some() { '*' } else { ' ' };
+
+        println!(
+            "{}{} {:>4}  {:<20}{}",
+            marker,
+            breakpoint,
+            address,
+            format_instruction(instruction),
+            source_comment(machine, address as u32)
+        );
+    }
+}
+
+fn set_target(machine: &mut Machine, target: &str, value: i64) -> Result<(), String> {
+    match target {
+        "pc" => {
+            let program_counter = check_index(value, "Address", address::MAX_POSSIBLE_COUNT as usize)?;
+            machine.set_program_counter(program_counter as u32);
+        },
+        "zero" => machine.set_zero_flag(value != 0),
+        "carry" => machine.set_carry_flag(value != 0),
+        _ => {
+            let register = parse_register(target)?;
+            if register == 0 {
+                return Err("r0 is always zero".to_string());
+            }
+
+            machine.registers_mut()[register] = value as u8;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_watchpoint(words: &[&str]) -> Result<Watchpoint, String> {
+    let kind = words.first().ok_or("Expected r<n>, mem or port")?;
+    let write = match words.get(2) {
+        Some(&"read") => false,
+        Some(&"write") | None => true,
+        Some(access) => return Err(format!("Unknown access \"{}\", expected read or write", access))
+    };
+
+    match *kind {
+        "mem" => {
+            let address = parse_index(words.get(1).ok_or("Expected an address")?, "Address", USABLE_MEMORY_SIZE)?;
+            Ok(if write { Watchpoint::MemoryWrite(address) } else { Watchpoint::MemoryRead(address) })
+        },
+        "port" => {
+            let port = match parse_number(words.get(1).ok_or("Expected a port")?)? {
+                port if (0..PORTS as i64).contains(&port) => port as usize,
+                port if (PORTS_ADDRESS as i64..MEMORY_SIZE as i64).contains(&port) => port as usize - PORTS_ADDRESS,
+                port => return Err(format!("Port {} out of range, expected 0-{} or {}-{}", port, PORTS - 1, PORTS_ADDRESS, MEMORY_SIZE - 1))
+            };
+
+            Ok(if write { Watchpoint::PortWrite(port) } else { Watchpoint::PortRead(port) })
+        },
+        register => Ok(Watchpoint::Register(parse_register(register)?))
+    }
+}
+
+fn format_watchpoint(watchpoint: &Watchpoint) -> String {
+    match watchpoint {
+        Watchpoint::MemoryRead(address) => format!("mem[{}] read", address),
+        Watchpoint::MemoryWrite(address) => format!("mem[{}] write", address),
+        Watchpoint::Register(register) => format!("r{}", register),
+        Watchpoint::PortRead(port) => format!("port {} read", port),
+        Watchpoint::PortWrite(port) => format!("port {} write", port)
+    }
+}
+
+fn parse_register(text: &str) -> Result<usize, String> {
+    match text.strip_prefix('r').and_then(|register| register.parse::<usize>().ok()) {
+        Some(register) if register < REGISTER_COUNT => Ok(register),
+        _ => Err(format!("Invalid register \"{}\"", text))
+    }
+}
+
+fn parse_location(machine: &Machine, text: &str) -> Result<u32, String> {
+    if let Ok(address) = parse_number(text) {
+        return Ok(check_index(address, "Address", address::MAX_POSSIBLE_COUNT as usize)? as u32);
+    }
+
+    let source_map = machine.source_map().ok_or_else(|| format!("Invalid address \"{}\", no source map is loaded", text))?;
+
+    if let Some((file, line)) = text.rsplit_once(':') {
+        let line = line.parse().map_err(|_| format!("Invalid line number \"{}\"", line))?;
+        return source_map.find_line(file, line).ok_or_else(|| format!("No code at or after {}:{}", file, line));
+    }
+
+    source_map.address_of(text).ok_or_else(|| format!("Unknown label \"{}\"", text))
+}
+
+fn parse_number(text: &str) -> Result<i64, String> {
+    let result = if let Some(hexadecimal) = text.strip_prefix("0x") {
+        i64::from_str_radix(hexadecimal, 16)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+
+    result.map_err(|_| format!("Invalid number \"{}\"", text))
+}
+
+fn parse_count(text: &str) -> Result<usize, String> {
+    match parse_number(text)? {
+        count if count < 0 => Err(format!("Expected a non-negative number, got {}", count)),
+        count => Ok(count as usize)
+    }
+}
+
+fn parse_index(text: &str, name: &str, limit: usize) -> Result<usize, String> {
+    check_index(parse_number(text)?, name, limit)
+}
+
+fn check_index(value: i64, name: &str, limit: usize) -> Result<usize, String> {
+    if value < 0 || value as u64 >= limit as u64 {
+        return Err(format!("{} {} out of range, expected 0-{}", name, value, limit - 1));
+    }
+
+    Ok(value as usize)
+}
\ No newline at end of file
@@ -0,0 +1,532 @@
+use batpu_emulator::machine::run::StopReason;
+#[cfg(feature = "serde")]
+use batpu_emulator::machine::Word;
+use batpu_emulator::machine::Machine;
+use batpu_emulator::machine_code::ExportFormat;
+use std::path::Path;
+use std::{env, fs, process};
+
+const USAGE: &str = "\
+Usage: batpu-run <program> [options]
+
+Options:
+  --cycles <n>          Maximum number of cycles to run (default 1000000)
+  --until-halt          Fail with a timeout if the program has not halted within the cycle budget
+  --inputs <file>       Controller input script, one \"<cycle> <buttons...>\" line per change
+  --dump <text|json>    Final state output format (default text), json needs the serde feature
+  --screen <ascii|pbm>  Screen format for text output (default ascii)
+  --emit <file>         Write the program as machine code before running, format chosen by
+                        extension: .mc text, .hex Intel HEX or .bin raw big-endian words";
+
+const EXIT_FAULT: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_TIMEOUT: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DumpFormat {
+    Text,
+    #[cfg(feature = "serde")]
+    Json
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScreenFormat {
+    Ascii,
+    Pbm
+}
+
+struct Options {
+    program: String,
+    cycles: u64,
+    until_halt: bool,
+    inputs: Option<String>,
+    dump: DumpFormat,
+    screen: ScreenFormat,
+    emit: Option<String>
+}
+
+struct Input {
+    cycle: u64,
+    buttons: u8
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1).collect()) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            process::exit(0);
+        },
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    let mut machine = Machine::new();
+    match machine.load_file(&options.program) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+        },
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+
+            process::exit(EXIT_FAULT);
+        }
+    }
+
+    let inputs = match &options.inputs {
+        Some(path) => match load_inputs(Path::new(path)) {
+            Ok(inputs) => inputs,
+            Err(error) => {
+                eprintln!("Failed to load {}: {}", path, error);
+                process::exit(EXIT_FAULT);
+            }
+        },
+        None => Vec::new()
+    };
+
+    if let Some(path) = &options.emit
+        && let Err(error) = emit(&machine, Path::new(path)) {
+        eprintln!("Failed to emit {}: {}", path, error);
+        process::exit(EXIT_FAULT);
+    }
+
+    let (reason, cycles) = run(&mut machine, options.cycles, &inputs);
+
+    let exit_code = match &reason {
+        StopReason::Fault(_) => EXIT_FAULT,
+        StopReason::CyclesExhausted if options.until_halt => EXIT_TIMEOUT,
+        _ => 0
+    };
+
+    match options.dump {
+        DumpFormat::Text => print!("{}", dump_text(&machine, &reason, cycles, options.screen)),
+        #[cfg(feature = "serde")]
+        DumpFormat::Json => match dump_json(&machine, &reason, cycles) {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                eprintln!("Failed to dump state: {}", error);
+                process::exit(EXIT_FAULT);
+            }
+        }
+    }
+
+    if let StopReason::Fault(error) = &reason {
+        eprintln!("Fault: {}", error);
+    } else if exit_code == EXIT_TIMEOUT {
+        eprintln!("Timeout: program did not halt within {} cycles", options.cycles);
+    }
+
+    process::exit(exit_code);
+}
+
+fn parse_options(arguments: Vec<String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        program: String::new(),
+        cycles: 1_000_000,
+        until_halt: false,
+        inputs: None,
+        dump: DumpFormat::Text,
+        screen: ScreenFormat::Ascii,
+        emit: None
+    };
+
+    let mut program = None;
+    let mut arguments = arguments.into_iter();
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--cycles" => {
+                let value = arguments.next().ok_or("--cycles expects a value")?;
+                options.cycles = value.parse().map_err(|_| format!("Invalid cycle count \"{}\"", value))?;
+            },
+            "--until-halt" => options.until_halt = true,
+            "--inputs" => options.inputs = Some(arguments.next().ok_or("--inputs expects a file")?),
+            "--dump" => {
+                options.dump = match arguments.next().as_deref() {
+                    Some("text") => DumpFormat::Text,
+                    #[cfg(feature = "serde")]
+                    Some("json") => DumpFormat::Json,
+                    #[cfg(not(feature = "serde"))]
+                    Some("json") => return Err("--dump json requires the serde feature".to_string()),
+                    _ => return Err("--dump expects text or json".to_string())
+                };
+            },
+            "--screen" => {
+                options.screen = match arguments.next().as_deref() {
+                    Some("ascii") => ScreenFormat::Ascii,
+                    Some("pbm") => ScreenFormat::Pbm,
+                    _ => return Err("--screen expects ascii or pbm".to_string())
+                };
+            },
+            "--emit" => {
+                let path = arguments.next().ok_or("--emit expects a file")?;
+                let extension = Path::new(&path).extension().and_then(|extension| extension.to_str()).unwrap_or("");
+                if ExportFormat::from_extension(extension).is_none() {
+                    return Err(format!("Cannot emit \"{}\", expected a .mc, .hex or .bin file", path));
+                }
+
+                options.emit = Some(path);
+            },
+            "--help" | "-h" => return Ok(None),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option \"{}\"", flag)),
+            _ => {
+                if program.is_some() {
+                    return Err(format!("Unexpected argument \"{}\"", argument));
+                }
+
+                program = Some(argument);
+            }
+        }
+    }
+
+    options.program = program.ok_or("Expected a program")?;
+    Ok(Some(options))
+}
+
+fn emit(machine: &Machine, path: &Path) -> Result<(), String> {
+    let format = path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ExportFormat::from_extension)
+        .ok_or("unknown output format")?;
+
+    let data = machine.export_program(format).map_err(|errors| {
+        errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+    })?;
+
+    fs::write(path, data).map_err(|error| error.to_string())
+}
+
+fn load_inputs(path: &Path) -> Result<Vec<Input>, String> {
+    let source = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let mut inputs = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let cycle = words.next().unwrap_or("");
+        let cycle = cycle.parse().map_err(|_| format!("Line {}: invalid cycle \"{}\"", index + 1, cycle))?;
+
+        let mut buttons = 0;
+        for button in words {
+            buttons |= match button.to_ascii_lowercase().as_str() {
+                "start" => 1 << 7,
+                "select" => 1 << 6,
+                "a" => 1 << 5,
+                "b" => 1 << 4,
+                "up" => 1 << 3,
+                "right" => 1 << 2,
+                "down" => 1 << 1,
+                "left" => 1,
+                "none" => 0,
+                _ => return Err(format!("Line {}: unknown button \"{}\"", index + 1, button))
+            };
+        }
+
+        inputs.push(Input {
+            cycle,
+            buttons
+        });
+    }
+
+    inputs.sort_by_key(|input| input.cycle);
+    Ok(inputs)
+}
+
+fn run(machine: &mut Machine, max_cycles: u64, inputs: &[Input]) -> (StopReason, u64) {
+    let mut cycles = 0;
+    let mut inputs = inputs.iter().peekable();
+
+    loop {
+        while let Some(input) = inputs.next_if(|input| input.cycle <= cycles) {
+            machine.controller_mut().set_binary(input.buttons);
+        }
+
+        let segment_end = match inputs.peek() {
+            Some(input) => input.cycle.min(max_cycles),
+            None => max_cycles
+        };
+
+        let result = machine.run_until_halt(segment_end - cycles);
+        cycles += result.cycles;
+
+        match result.reason {
+            StopReason::CyclesExhausted if cycles < max_cycles => continue,
+            reason => return (reason, cycles)
+        }
+    }
+}
+
+fn stop_reason(reason: &StopReason) -> &'static str {
+    match reason {
+        StopReason::Halted => "halted",
+        StopReason::CyclesExhausted => "cycles_exhausted",
+        StopReason::Breakpoint(_) => "breakpoint",
+        StopReason::Watchpoint { .. } => "watchpoint",
+        StopReason::FramePushed => "frame_pushed",
+        StopReason::Condition => "condition",
+        StopReason::Fault(_) => "fault"
+    }
+}
+
+fn screen_rows(machine: &Machine) -> Vec<String> {
+    let screen = machine.screen();
+
+    (0..screen.height() as isize)
+        .rev()
+        .map(|y| {
+            (0..screen.width() as isize)
+                .map(|x| if screen.pixel(x, y) { '#' } else { '.' })
+                .collect()
+        })
+        .collect()
+}
+
+fn dump_text(machine: &Machine, reason: &StopReason, cycles: u64, screen_format: ScreenFormat) -> String {
+    let mut text = String::new();
+
+    text.push_str(&format!("stop: {}\n", stop_reason(reason)));
+    if let StopReason::Fault(error) = reason {
+        text.push_str(&format!("fault: {}\n", error));
+    }
+
+    text.push_str(&format!("cycles: {}\n", cycles));
+    text.push_str(&format!("program_counter: {}\n", machine.program_counter()));
+    text.push_str(&format!("halt: {}\n", machine.halt() as u8));
+    text.push_str(&format!("zero_flag: {}\n", machine.zero_flag() as u8));
+    text.push_str(&format!("carry_flag: {}\n", machine.carry_flag() as u8));
+
+    let registers: Vec<String> = machine.registers().iter().map(|value| value.to_string()).collect();
+    text.push_str(&format!("registers: {}\n", registers.join(" ")));
+
+    text.push_str("memory:\n");
+    for (row, values) in machine.memory().chunks(16).enumerate() {
+        let values: Vec<String> = values.iter().map(|value| format!("{:02x}", value)).collect();
+        text.push_str(&format!("  {:>3}: {}\n", row * 16, values.join(" ")));
+    }
+
+    let stack: Vec<String> = machine.stack().stack().iter().map(|address| address.to_string()).collect();
+    text.push_str(&format!("stack: {}\n", stack.join(" ")));
+    text.push_str(&format!("stack_high_water_mark: {}\n", machine.stack().high_water_mark()));
+
+    text.push_str(&format!("characters: \"{}\"\n", machine.character_display().data()));
+    text.push_str(&format!("number: {}\n", machine.number_display().value()));
+
+    text.push_str("screen:\n");
+    let rows = screen_rows(machine);
+    match screen_format {
+        ScreenFormat::Ascii => {
+            for row in rows {
+                text.push_str(&row);
+                text.push('\n');
+            }
+        },
+        ScreenFormat::Pbm => {
+            text.push_str(&format!("P1\n{} {}\n", machine.screen().width(), machine.screen().height()));
+            for row in rows {
+                let pixels: Vec<&str> = row.chars().map(|pixel| if pixel == '#' { "1" } else { "0" }).collect();
+                text.push_str(&pixels.join(" "));
+                text.push('\n');
+            }
+        }
+    }
+
+    text
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct Dump<'a> {
+    stop: &'static str,
+    fault: Option<String>,
+    cycles: u64,
+    program_counter: u32,
+    halt: bool,
+    zero_flag: bool,
+    carry_flag: bool,
+    registers: &'a [Word],
+    memory: &'a [Word],
+    stack: &'a [u32],
+    stack_high_water_mark: usize,
+    characters: &'a str,
+    number: i32,
+    screen: Vec<String>
+}
+
+#[cfg(feature = "serde")]
+fn dump_json(machine: &Machine, reason: &StopReason, cycles: u64) -> Result<String, String> {
+    let dump = Dump {
+        stop: stop_reason(reason),
+        fault: match reason {
+            StopReason::Fault(error) => Some(error.to_string()),
+            _ => None
+        },
+        cycles,
+        program_counter: machine.program_counter(),
+        halt: machine.halt(),
+        zero_flag: machine.zero_flag(),
+        carry_flag: machine.carry_flag(),
+        registers: machine.registers(),
+        memory: machine.memory(),
+        stack: machine.stack().stack(),
+        stack_high_water_mark: machine.stack().high_water_mark(),
+        characters: machine.character_display().data(),
+        number: machine.number_display().value(),
+        screen: screen_rows(machine)
+    };
+
+    serde_json::to_string(&dump).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batpu_assembly::components::address::Address;
+    use batpu_assembly::components::immediate::Immediate;
+    use batpu_assembly::components::location::Location;
+    use batpu_assembly::components::offset::Offset;
+    use batpu_assembly::components::register::Register;
+    use batpu_assembly::instruction::Instruction;
+
+    fn parse(arguments: &[&str]) -> Result<Option<Options>, String> {
+        parse_options(arguments.iter().map(|argument| argument.to_string()).collect())
+    }
+
+    fn inputs(name: &str, source: &str) -> Result<Vec<Input>, String> {
+        let path = env::temp_dir().join(format!("batpu-run-{}-{}.txt", name, process::id()));
+        fs::write(&path, source).unwrap();
+
+        let inputs = load_inputs(&path);
+        fs::remove_file(&path).unwrap();
+        inputs
+    }
+
+    fn controller_loop() -> Machine {
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![
+            Instruction::LoadImmediate(Register::new(1), Immediate::new(255)),
+            Instruction::MemoryLoad(Register::new(1), Register::new(2), Offset::new(0)),
+            Instruction::Jump(Location::Address(Address::new(1)))
+        ]);
+        machine
+    }
+
+    #[test]
+    fn parses_defaults() {
+        let options = parse(&["program.as"]).unwrap().unwrap();
+        assert_eq!(options.program, "program.as");
+        assert_eq!(options.cycles, 1_000_000);
+        assert!(!options.until_halt);
+        assert!(options.inputs.is_none());
+        assert!(options.dump == DumpFormat::Text);
+        assert!(options.screen == ScreenFormat::Ascii);
+        assert!(options.emit.is_none());
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let options = parse(&["--cycles", "50", "--until-halt", "--inputs", "inputs.txt", "--screen", "pbm", "--emit", "out.hex", "program.as"]).unwrap().unwrap();
+        assert_eq!(options.program, "program.as");
+        assert_eq!(options.cycles, 50);
+        assert!(options.until_halt);
+        assert_eq!(options.inputs.as_deref(), Some("inputs.txt"));
+        assert!(options.screen == ScreenFormat::Pbm);
+        assert_eq!(options.emit.as_deref(), Some("out.hex"));
+    }
+
+    #[test]
+    fn help_is_not_an_error() {
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["program.as", "-h"]).unwrap().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn parses_json_dumps() {
+        assert!(parse(&["--dump", "json", "program.as"]).unwrap().unwrap().dump == DumpFormat::Json);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(parse(&[]).err().as_deref(), Some("Expected a program"));
+        assert_eq!(parse(&["a.as", "b.as"]).err().as_deref(), Some("Unexpected argument \"b.as\""));
+        assert_eq!(parse(&["--fast", "a.as"]).err().as_deref(), Some("Unknown option \"--fast\""));
+        assert_eq!(parse(&["a.as", "--cycles"]).err().as_deref(), Some("--cycles expects a value"));
+        assert_eq!(parse(&["a.as", "--cycles", "-1"]).err().as_deref(), Some("Invalid cycle count \"-1\""));
+        assert_eq!(parse(&["a.as", "--dump", "xml"]).err().as_deref(), Some("--dump expects text or json"));
+        assert_eq!(parse(&["a.as", "--screen", "png"]).err().as_deref(), Some("--screen expects ascii or pbm"));
+        assert_eq!(parse(&["a.as", "--emit", "out.txt"]).err().as_deref(), Some("Cannot emit \"out.txt\", expected a .mc, .hex or .bin file"));
+    }
+
+    #[test]
+    fn loads_sorted_inputs() {
+        let inputs = inputs("sorted", "# script\n10 A start\n\n0 left  # hold left\n5 none\n").unwrap();
+        let inputs: Vec<(u64, u8)> = inputs.iter().map(|input| (input.cycle, input.buttons)).collect();
+        assert_eq!(inputs, vec![(0, 1), (5, 0), (10, 0b1010_0000)]);
+    }
+
+    #[test]
+    fn rejects_bad_inputs() {
+        assert_eq!(inputs("cycle", "0 up\nsoon a\n").err().as_deref(), Some("Line 2: invalid cycle \"soon\""));
+        assert_eq!(inputs("button", "0 jump\n").err().as_deref(), Some("Line 1: unknown button \"jump\""));
+        assert!(load_inputs(Path::new("/nonexistent/batpu-run-inputs.txt")).is_err());
+    }
+
+    #[test]
+    fn applies_inputs_at_their_cycle() {
+        let script = [
+            Input { cycle: 0, buttons: 1 },
+            Input { cycle: 5, buttons: 0b0010_0000 }
+        ];
+
+        let mut machine = controller_loop();
+        let (reason, cycles) = run(&mut machine, 4, &script);
+        assert_eq!(reason, StopReason::CyclesExhausted);
+        assert_eq!(cycles, 4);
+        assert_eq!(machine.registers()[2], 1);
+
+        let mut machine = controller_loop();
+        let (reason, cycles) = run(&mut machine, 10, &script);
+        assert_eq!(reason, StopReason::CyclesExhausted);
+        assert_eq!(cycles, 10);
+        assert_eq!(machine.registers()[2], 0b0010_0000);
+    }
+
+    #[test]
+    fn stops_on_halt() {
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![Instruction::NoOperation, Instruction::Halt]);
+
+        let (reason, cycles) = run(&mut machine, 100, &[Input { cycle: 50, buttons: 1 }]);
+        assert_eq!(reason, StopReason::Halted);
+        assert_eq!(cycles, 2);
+        assert_eq!(machine.controller().binary(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn dumps_valid_json() {
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![Instruction::NoOperation, Instruction::Jump(Location::Label(".end".to_string()))]);
+        let (reason, cycles) = run(&mut machine, 10, &[]);
+
+        let json: serde_json::Value = serde_json::from_str(&dump_json(&machine, &reason, cycles).unwrap()).unwrap();
+        assert_eq!(json["stop"], "fault");
+        assert_eq!(json["fault"], "Unresolved label \".end\" at address 1");
+        assert_eq!(json["cycles"], 1);
+        assert_eq!(json["program_counter"], 1);
+        assert_eq!(json["characters"], "");
+        assert_eq!(json["registers"].as_array().unwrap().len(), 16);
+        assert_eq!(json["memory"].as_array().unwrap().len(), 240);
+        assert_eq!(json["screen"][0], ".".repeat(32));
+    }
+}
\ No newline at end of file
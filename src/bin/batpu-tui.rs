@@ -0,0 +1,170 @@
+use batpu_emulator::machine::clock::{Clock, ClockMode, DEFAULT_FREQUENCY};
+use batpu_emulator::machine::run::StopReason;
+use batpu_emulator::machine::Machine;
+use batpu_emulator::tui::{Button, Dashboard, BUTTONS};
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use std::time::{Duration, Instant};
+use std::{env, io, process, thread};
+
+const FRAME_TIME: Duration = Duration::from_millis(16);
+const BUTTON_HOLD_TIME: Duration = Duration::from_millis(150);
+const SLOW_MOTION_FACTOR: f64 = 0.1;
+
+fn main() {
+    let arguments: Vec<String> = env::args().collect();
+    if arguments.len() < 2 || arguments.len() > 3 {
+        eprintln!("Usage: {} <program> [frequency in Hz]", arguments[0]);
+        process::exit(2);
+    }
+
+    let frequency = match arguments.get(2) {
+        Some(frequency) => match frequency.parse::<f64>() {
+            Ok(frequency) if frequency > 0.0 => frequency,
+            _ => {
+                eprintln!("Invalid frequency \"{}\"", frequency);
+                process::exit(2);
+            }
+        },
+        None => DEFAULT_FREQUENCY
+    };
+
+    let mut machine = Machine::new();
+    match machine.load_file(&arguments[1]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+        },
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+
+            process::exit(1);
+        }
+    }
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut machine, Clock::new(frequency));
+    ratatui::restore();
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(terminal: &mut ratatui::DefaultTerminal, machine: &mut Machine, mut clock: Clock) -> io::Result<()> {
+    let mut dashboard = Dashboard::new();
+    let mut held: [Option<Instant>; 8] = [None; 8];
+
+    let mut resume_mode = ClockMode::Normal;
+    let mut stopped: Option<String> = None;
+    let mut redraw = true;
+
+    loop {
+        let frame_start = Instant::now();
+
+        while event::poll(Duration::ZERO)? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::Resize(_, _) => {
+                    redraw = true;
+                    continue;
+                },
+                _ => continue
+            };
+
+            if key.kind == KeyEventKind::Release {
+                if let Some(button) = Button::from_key(key.code) {
+                    held[button_index(button)] = None;
+                }
+
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Char('p') => {
+                    if clock.is_paused() {
+                        clock.set_mode(resume_mode);
+                    } else {
+                        resume_mode = clock.mode();
+                        clock.set_mode(ClockMode::Paused);
+                    }
+                },
+                KeyCode::Char('t') => clock.set_mode(toggle(clock.mode(), ClockMode::Turbo)),
+                KeyCode::Char('m') => clock.set_mode(toggle(clock.mode(), ClockMode::SlowMotion(SLOW_MOTION_FACTOR))),
+                KeyCode::Char('r') => {
+                    machine.reset();
+                    clock.sync();
+                    stopped = None;
+                },
+                code => {
+                    if let Some(button) = Button::from_key(code) {
+                        held[button_index(button)] = Some(frame_start + BUTTON_HOLD_TIME);
+                    }
+                }
+            }
+        }
+
+        for button in BUTTONS {
+            let pressed = held[button_index(button)].is_some_and(|until| until > frame_start);
+            button.set(machine.controller_mut(), pressed);
+        }
+
+        if stopped.is_none() {
+            let result = clock.run(machine);
+
+            stopped = match result.reason {
+                StopReason::Halted => Some("Halted".to_string()),
+                StopReason::Fault(error) => Some(format!("Fault: {}", error)),
+                _ => None
+            };
+        }
+
+        let status = match (&stopped, clock.mode()) {
+            (Some(reason), _) => format!("{} after {} cycles\n[r] reset  [esc] quit", reason, machine.cycles()),
+            (None, ClockMode::Paused) => format!("Paused at {}, cycle {}\n[p] resume  [esc] quit", machine.program_counter(), machine.cycles()),
+            (None, mode) => format!(
+                "{} at {}, cycle {}\n[p] pause  [t] turbo  [m] slow motion  [esc] quit",
+                mode_name(mode),
+                machine.program_counter(),
+                machine.cycles()
+            )
+        };
+
+        if dashboard.update(machine) | dashboard.set_status(status) | redraw {
+            terminal.draw(|frame| dashboard.render(frame))?;
+            redraw = false;
+        }
+
+        let elapsed = frame_start.elapsed();
+        if elapsed < FRAME_TIME {
+            thread::sleep(FRAME_TIME - elapsed);
+        }
+    }
+}
+
+fn toggle(current: ClockMode, mode: ClockMode) -> ClockMode {
+    if current == mode {
+        ClockMode::Normal
+    } else {
+        mode
+    }
+}
+
+fn mode_name(mode: ClockMode) -> &'static str {
+    match mode {
+        ClockMode::Normal => "Running",
+        ClockMode::Turbo => "Turbo",
+        ClockMode::Paused => "Paused",
+        ClockMode::SlowMotion(_) => "Slow motion"
+    }
+}
+
+fn button_index(button: Button) -> usize {
+    BUTTONS.iter().position(|&candidate| candidate == button).unwrap()
+}
\ No newline at end of file
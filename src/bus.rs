@@ -0,0 +1,180 @@
+use crate::machine::{Word, PORTS};
+
+pub const PORT_NAMES: [&str; PORTS] = [
+    "pixel_x",
+    "pixel_y",
+    "draw_pixel",
+    "clear_pixel",
+    "load_pixel",
+    "buffer_screen",
+    "clear_screen_buffer",
+    "write_char",
+    "buffer_chars",
+    "clear_chars_buffer",
+    "show_number",
+    "clear_number",
+    "signed_mode",
+    "unsigned_mode",
+    "rng",
+    "controller_input"
+];
+
+pub trait Device: Send {
+    fn read(&mut self, register: usize) -> Word;
+    fn write(&mut self, register: usize, value: Word);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceId {
+    Screen,
+    CharacterDisplay,
+    NumberDisplay,
+    Random,
+    Controller,
+    Custom(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortMapping {
+    pub device: DeviceId,
+    pub register: usize
+}
+
+impl PortMapping {
+    pub fn new(device: DeviceId, register: usize) -> Self {
+        Self {
+            device,
+            register
+        }
+    }
+    
+    pub fn standard(port: usize) -> Option<Self> {
+        match port {
+            0..=6   => Some(Self::new(DeviceId::Screen, port)),
+            7..=9   => Some(Self::new(DeviceId::CharacterDisplay, port - 7)),
+            10..=13 => Some(Self::new(DeviceId::NumberDisplay, port - 10)),
+            14      => Some(Self::new(DeviceId::Random, 0)),
+            15      => Some(Self::new(DeviceId::Controller, 0)),
+            _ => None
+        }
+    }
+}
+
+pub struct Bus {
+    ports: [Option<PortMapping>; PORTS],
+    devices: Vec<Box<dyn Device>>
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        let mut bus = Self::empty();
+        for port in 0..PORTS {
+            bus.ports[port] = PortMapping::standard(port);
+        }
+        
+        bus
+    }
+    
+    pub fn empty() -> Self {
+        Self {
+            ports: [None; PORTS],
+            devices: Vec::new()
+        }
+    }
+    
+    pub fn port(&self, port: usize) -> Option<PortMapping> {
+        self.ports.get(port).copied().flatten()
+    }
+    
+    pub fn map(&mut self, port: usize, device: DeviceId, register: usize) -> bool {
+        if port >= PORTS {
+            return false;
+        }
+        
+        if let DeviceId::Custom(index) = device && index >= self.devices.len() {
+            return false;
+        }
+        
+        self.ports[port] = Some(PortMapping::new(device, register));
+        true
+    }
+    
+    pub fn unmap(&mut self, port: usize) -> Option<PortMapping> {
+        self.ports.get_mut(port).and_then(Option::take)
+    }
+    
+    pub fn map_standard(&mut self, port: usize) -> bool {
+        if port >= PORTS {
+            return false;
+        }
+        
+        self.ports[port] = PortMapping::standard(port);
+        true
+    }
+    
+    pub fn attach(&mut self, device: Box<dyn Device>) -> DeviceId {
+        self.devices.push(device);
+        DeviceId::Custom(self.devices.len() - 1)
+    }
+    
+    pub fn device(&self, index: usize) -> Option<&dyn Device> {
+        self.devices.get(index).map(|device| device.as_ref())
+    }
+    
+    pub fn device_mut(&mut self, index: usize) -> Option<&mut dyn Device> {
+        match self.devices.get_mut(index) {
+            Some(device) => Some(device.as_mut()),
+            None => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ldi, load, machine, port, store};
+
+    struct Offsetting(Word);
+
+    impl Device for Offsetting {
+        fn read(&mut self, register: usize) -> Word {
+            self.0 + register as Word
+        }
+
+        fn write(&mut self, _register: usize, value: Word) {
+            self.0 = value;
+        }
+    }
+
+    #[test]
+    fn routes_ports_to_custom_devices() {
+        let mut machine = machine(vec![ldi(1, port(3)), ldi(2, 9), store(1, 2), load(1, 3)]);
+        let device = machine.bus_mut().attach(Box::new(Offsetting(0)));
+        assert!(machine.bus_mut().map(3, device, 5));
+
+        for _ in 0..4 {
+            machine.tick().unwrap();
+        }
+
+        assert_eq!(machine.registers()[3], 14);
+        assert_eq!(machine.bus().port(3), Some(PortMapping::new(device, 5)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_mappings() {
+        let mut bus = Bus::new();
+        assert!(!bus.map(PORTS, DeviceId::Screen, 0));
+        assert!(!bus.map_standard(PORTS));
+        assert!(!bus.map(0, DeviceId::Custom(0), 0));
+        assert_eq!(bus.port(0), PortMapping::standard(0));
+
+        let device = bus.attach(Box::new(Offsetting(0)));
+        assert_eq!(device, DeviceId::Custom(0));
+        assert!(bus.map(0, device, 0));
+        assert!(!bus.map(1, DeviceId::Custom(1), 0));
+        assert_eq!(bus.unmap(0), Some(PortMapping::new(device, 0)));
+        assert_eq!(bus.port(0), None);
+    }
+}
\ No newline at end of file
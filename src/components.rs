@@ -2,4 +2,5 @@ pub mod screen;
 pub mod character_display;
 pub mod number_display;
 pub mod controller;
-pub mod stack;
\ No newline at end of file
+pub mod stack;
+pub mod random;
\ No newline at end of file
@@ -1,5 +1,22 @@
+use crate::bus::Device;
+use crate::machine::config::ConfigError;
+use crate::machine::Word;
+
+pub const DEFAULT_CHARACTERS: &[char] = &[' ', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '.', '!', '?'];
+
+pub const WRITE_CHARACTER: usize = 0;
+pub const PUSH_BUFFER: usize = 1;
+pub const CLEAR_BUFFER: usize = 2;
+
+pub const MAX_CAPACITY: usize = 1 << 16;
+pub const MAX_CHARACTERS: usize = Word::MAX as usize + 1;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "CharacterDisplayData", try_from = "CharacterDisplayData"))]
 pub struct CharacterDisplay {
     capacity: usize,
+    characters: Vec<char>,
     
     buffer: String,
     
@@ -9,8 +26,13 @@ pub struct CharacterDisplay {
 
 impl CharacterDisplay {
     pub fn new(capacity: usize) -> Self {
+        Self::with_characters(capacity, DEFAULT_CHARACTERS.to_vec())
+    }
+    
+    pub fn with_characters(capacity: usize, characters: Vec<char>) -> Self {
         Self {
             capacity,
+            characters,
             
             buffer: String::with_capacity(capacity),
             
@@ -19,12 +41,36 @@ impl CharacterDisplay {
         }
     }
     
+    pub fn check_capacity(capacity: usize) -> Result<(), ConfigError> {
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(ConfigError::CharacterCapacity(capacity));
+        }
+        
+        Ok(())
+    }
+    
+    pub fn check_characters(characters: &[char]) -> Result<(), ConfigError> {
+        if characters.is_empty() || characters.len() > MAX_CHARACTERS {
+            return Err(ConfigError::CharacterSet(characters.len()));
+        }
+        
+        Ok(())
+    }
+    
     pub fn capacity(&self) -> usize {
         self.capacity
     }
     
+    pub fn characters(&self) -> &[char] {
+        &self.characters
+    }
+    
+    pub fn character(&self, code: Word) -> Option<char> {
+        self.characters.get(code as usize).copied()
+    }
+    
     pub fn push(&mut self, character: Option<&char>) -> bool {
-        if self.buffer.len() == self.capacity {
+        if self.buffer.chars().count() == self.capacity {
             return false;
         }
         
@@ -37,10 +83,49 @@ impl CharacterDisplay {
         }
     }
     
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+    
     pub fn data(&self) -> &str {
         &self.data
     }
     
+    pub(crate) fn set_contents(&mut self, buffer: &str, data: &str) {
+        self.buffer.clear();
+        self.buffer.push_str(buffer);
+        
+        self.data.clear();
+        self.data.push_str(data);
+        self.data_updated = true;
+    }
+    
+    pub(crate) fn check_contents(&self) -> Result<(), String> {
+        for (name, text) in [("buffer", &self.buffer), ("data", &self.data)] {
+            let length = text.chars().count();
+            if length > self.capacity {
+                return Err(format!("Character {} holds {} characters, expected at most {}", name, length, self.capacity));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    pub(crate) fn truncate_buffer(&mut self, length: usize) {
+        self.buffer.truncate(length);
+    }
+    
+    pub(crate) fn set_buffer(&mut self, buffer: &str) {
+        self.buffer.clear();
+        self.buffer.push_str(buffer);
+    }
+    
+    pub(crate) fn set_data(&mut self, data: &str) {
+        self.data.clear();
+        self.data.push_str(data);
+        self.data_updated = true;
+    }
+    
     pub fn push_buffer(&mut self) {
         self.data.clone_from(&self.buffer);
         self.data_updated = true;
@@ -57,6 +142,14 @@ impl CharacterDisplay {
         self.data_updated = true;
     }
 
+    pub(crate) fn restore(&mut self, saved: CharacterDisplay) {
+        let characters = std::mem::take(&mut self.characters);
+        
+        *self = saved;
+        self.characters = characters;
+        self.data_updated = true;
+    }
+
     pub fn data_updated(&self) -> bool {
         self.data_updated
     }
@@ -64,4 +157,93 @@ impl CharacterDisplay {
     pub fn disable_data_updated(&mut self) {
         self.data_updated = false;
     }
+}
+
+impl Device for CharacterDisplay {
+    fn read(&mut self, _register: usize) -> Word {
+        0
+    }
+
+    fn write(&mut self, register: usize, value: Word) {
+        match register {
+            WRITE_CHARACTER => { self.push(self.character(value).as_ref()); },
+            PUSH_BUFFER => self.push_buffer(),
+            CLEAR_BUFFER => self.clear_buffer(),
+            _ => ()
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CharacterDisplayData {
+    capacity: usize,
+    
+    buffer: String,
+    data: String
+}
+
+#[cfg(feature = "serde")]
+impl From<CharacterDisplay> for CharacterDisplayData {
+    fn from(character_display: CharacterDisplay) -> Self {
+        Self {
+            capacity: character_display.capacity,
+            
+            buffer: character_display.buffer,
+            data: character_display.data
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CharacterDisplayData> for CharacterDisplay {
+    type Error = String;
+
+    fn try_from(data: CharacterDisplayData) -> Result<Self, Self::Error> {
+        CharacterDisplay::check_capacity(data.capacity).map_err(|error| error.to_string())?;
+        
+        let mut character_display = CharacterDisplay::new(data.capacity);
+        character_display.set_contents(&data.buffer, &data.data);
+        character_display.check_contents()?;
+        
+        Ok(character_display)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use serde_test::{assert_de_tokens_error, assert_ser_tokens, Token};
+
+    fn tokens(capacity: u64, buffer: &'static str, data: &'static str) -> Vec<Token> {
+        vec![
+            Token::Struct { name: "CharacterDisplayData", len: 3 },
+            Token::Str("capacity"),
+            Token::U64(capacity),
+            Token::Str("buffer"),
+            Token::String(buffer),
+            Token::Str("data"),
+            Token::String(data),
+            Token::StructEnd
+        ]
+    }
+
+    #[test]
+    fn serializes_the_display() {
+        let mut character_display = CharacterDisplay::new(3);
+        character_display.push(Some(&'H'));
+        character_display.push(Some(&'I'));
+        character_display.push_buffer();
+        character_display.clear_buffer();
+        character_display.push(Some(&'!'));
+
+        assert_ser_tokens(&character_display, &tokens(3, "!", "HI"));
+    }
+
+    #[test]
+    fn rejects_invalid_displays() {
+        assert_de_tokens_error::<CharacterDisplay>(&tokens(2, "ABC", ""), "Character buffer holds 3 characters, expected at most 2");
+        assert_de_tokens_error::<CharacterDisplay>(&tokens(2, "", "ABC"), "Character data holds 3 characters, expected at most 2");
+        assert_de_tokens_error::<CharacterDisplay>(&tokens(0, "", ""), &ConfigError::CharacterCapacity(0).to_string());
+    }
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
+use crate::bus::Device;
 use crate::machine::Word;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Controller {
     pub start: bool,
     pub select: bool,
@@ -58,4 +61,25 @@ impl Controller {
         
         binary
     }
+    
+    pub fn set_binary(&mut self, binary: Word) {
+        self.start = (binary >> 7) & 1 != 0;
+        self.select = (binary >> 6) & 1 != 0;
+
+        self.a = (binary >> 5) & 1 != 0;
+        self.b = (binary >> 4) & 1 != 0;
+
+        self.up = (binary >> 3) & 1 != 0;
+        self.right = (binary >> 2) & 1 != 0;
+        self.down = (binary >> 1) & 1 != 0;
+        self.left = binary & 1 != 0;
+    }
+}
+
+impl Device for Controller {
+    fn read(&mut self, _register: usize) -> Word {
+        self.binary()
+    }
+
+    fn write(&mut self, _register: usize, _value: Word) {}
 }
\ No newline at end of file
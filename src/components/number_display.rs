@@ -1,9 +1,18 @@
+use crate::bus::Device;
 use crate::machine::Word;
 
+pub const SHOW_NUMBER: usize = 0;
+pub const CLEAR_NUMBER: usize = 1;
+pub const SIGNED_MODE: usize = 2;
+pub const UNSIGNED_MODE: usize = 3;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NumberDisplay {
     pub signed: bool,
 
     value: Word,
+    #[cfg_attr(feature = "serde", serde(skip))]
     value_updated: bool
 }
 
@@ -25,6 +34,10 @@ impl NumberDisplay {
         }
     }
     
+    pub fn raw_value(&self) -> Word {
+        self.value
+    }
+    
     pub fn set_value(&mut self, value: Word) {
         self.value = value;
         self.value_updated = true;
@@ -37,6 +50,11 @@ impl NumberDisplay {
         self.value_updated = true;
     }
 
+    pub(crate) fn restore(&mut self, saved: NumberDisplay) {
+        *self = saved;
+        self.value_updated = true;
+    }
+
     pub fn value_updated(&self) -> bool {
         self.value_updated
     }
@@ -44,4 +62,20 @@ impl NumberDisplay {
     pub fn disable_value_updated(&mut self) {
         self.value_updated = false;
     }
+}
+
+impl Device for NumberDisplay {
+    fn read(&mut self, _register: usize) -> Word {
+        0
+    }
+
+    fn write(&mut self, register: usize, value: Word) {
+        match register {
+            SHOW_NUMBER => self.set_value(value),
+            CLEAR_NUMBER => self.clear(),
+            SIGNED_MODE => self.signed = true,
+            UNSIGNED_MODE => self.signed = false,
+            _ => ()
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+use crate::bus::Device;
+use crate::machine::Word;
+use rand::{rng, Rng};
+
+pub const DEFAULT_SEED: u64 = 0x4261_7450_5532_5345;
+
+pub trait RandomSource: Send {
+    fn next_word(&mut self) -> Word;
+    
+    fn state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    
+    fn set_state(&mut self, _state: &[u8]) -> bool {
+        false
+    }
+}
+
+pub struct SeededRandom {
+    seed: u64,
+    state: u64
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed
+        }
+    }
+    
+    pub fn from_entropy() -> Self {
+        Self::new(rng().random())
+    }
+    
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.state = seed;
+    }
+    
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_word(&mut self) -> Word {
+        (self.next_u64() >> (u64::BITS - Word::BITS)) as Word
+    }
+    
+    fn state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(16);
+        state.extend_from_slice(&self.seed.to_le_bytes());
+        state.extend_from_slice(&self.state.to_le_bytes());
+        
+        state
+    }
+    
+    fn set_state(&mut self, state: &[u8]) -> bool {
+        if state.len() != 16 {
+            return false;
+        }
+        
+        self.seed = u64::from_le_bytes(state[0..8].try_into().unwrap());
+        self.state = u64::from_le_bytes(state[8..16].try_into().unwrap());
+        
+        true
+    }
+}
+
+impl Device for Box<dyn RandomSource> {
+    fn read(&mut self, _register: usize) -> Word {
+        self.next_word()
+    }
+
+    fn write(&mut self, _register: usize, _value: Word) {}
+}
\ No newline at end of file
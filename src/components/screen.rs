@@ -1,3 +1,20 @@
+use crate::bus::Device;
+use crate::machine::config::ConfigError;
+use crate::machine::Word;
+
+pub const X: usize = 0;
+pub const Y: usize = 1;
+pub const DRAW_PIXEL: usize = 2;
+pub const CLEAR_PIXEL: usize = 3;
+pub const LOAD_PIXEL: usize = 4;
+pub const PUSH_BUFFER: usize = 5;
+pub const CLEAR_BUFFER: usize = 6;
+
+pub const MAX_SIDE: usize = Word::MAX as usize + 1;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "ScreenData", try_from = "ScreenData"))]
 pub struct Screen {
     pub x: isize,
     pub y: isize,
@@ -29,6 +46,14 @@ impl Screen {
         }
     }
     
+    pub fn check_size(width: usize, height: usize) -> Result<(), ConfigError> {
+        if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+            return Err(ConfigError::ScreenSize(width, height));
+        }
+        
+        Ok(())
+    }
+    
     pub fn width(&self) -> usize {
         self.width
     }
@@ -38,13 +63,21 @@ impl Screen {
     }
     
     pub fn pix(&self) -> bool {
-        let (byte, bit) = self.get_index(self.x, self.y);
-
-        ((self.buffer[byte] >> bit) & 1) != 0
+        self.buffer_pixel(self.x, self.y)
     }
 
     pub fn set_pix(&mut self, value: bool) {
-        let (byte, bit) = self.get_index(self.x, self.y);
+        self.set_buffer_pixel(self.x, self.y, value);
+    }
+    
+    pub fn buffer_pixel(&self, x: isize, y: isize) -> bool {
+        let (byte, bit) = self.get_index(x, y);
+
+        ((self.buffer[byte] >> bit) & 1) != 0
+    }
+    
+    pub(crate) fn set_buffer_pixel(&mut self, x: isize, y: isize, value: bool) {
+        let (byte, bit) = self.get_index(x, y);
         
         if value {
             self.buffer[byte] |= 1 << bit;
@@ -69,10 +102,47 @@ impl Screen {
         self.image_updated = true;
     }
 
+    pub fn pixel(&self, x: isize, y: isize) -> bool {
+        let (byte, bit) = self.get_index(x, y);
+
+        ((self.image[byte] >> bit) & 1) != 0
+    }
+
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
     pub fn image(&self) -> &[u8] {
         &self.image
     }
     
+    pub(crate) fn set_contents(&mut self, buffer: &[u8], image: &[u8]) -> bool {
+        if buffer.len() != self.buffer.len() || image.len() != self.image.len() {
+            return false;
+        }
+        
+        self.buffer.copy_from_slice(buffer);
+        
+        self.image.copy_from_slice(image);
+        self.image_updated = true;
+        
+        true
+    }
+    
+    pub(crate) fn set_buffer(&mut self, buffer: &[u8]) {
+        self.buffer.copy_from_slice(buffer);
+    }
+    
+    pub(crate) fn set_image(&mut self, image: &[u8]) {
+        self.image.copy_from_slice(image);
+        self.image_updated = true;
+    }
+    
+    pub(crate) fn restore(&mut self, saved: Screen) {
+        *self = saved;
+        self.image_updated = true;
+    }
+
     pub fn image_updated(&self) -> bool {
         self.image_updated
     }
@@ -92,4 +162,192 @@ impl Screen {
 
         (byte, bit)
     }
+}
+
+impl Device for Screen {
+    fn read(&mut self, register: usize) -> Word {
+        match register {
+            LOAD_PIXEL => self.pix() as Word,
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, register: usize, value: Word) {
+        match register {
+            X => self.x = value as isize,
+            Y => self.y = value as isize,
+            DRAW_PIXEL => self.set_pix(true),
+            CLEAR_PIXEL => self.set_pix(false),
+            PUSH_BUFFER => self.push_buffer(),
+            CLEAR_BUFFER => self.clear_buffer(),
+            _ => ()
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ScreenData {
+    x: isize,
+    y: isize,
+    
+    width: usize,
+    height: usize,
+    
+    buffer: Vec<String>,
+    image: Vec<String>
+}
+
+#[cfg(feature = "serde")]
+impl From<Screen> for ScreenData {
+    fn from(screen: Screen) -> Self {
+        Self {
+            x: screen.x,
+            y: screen.y,
+            
+            width: screen.width,
+            height: screen.height,
+            
+            buffer: screen.rows(&screen.buffer),
+            image: screen.rows(&screen.image)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ScreenData> for Screen {
+    type Error = String;
+
+    fn try_from(data: ScreenData) -> Result<Self, Self::Error> {
+        Screen::check_size(data.width, data.height).map_err(|error| error.to_string())?;
+        
+        let mut screen = Screen::new(data.width, data.height);
+        
+        screen.x = data.x;
+        screen.y = data.y;
+        
+        let buffer = screen.pack(&data.buffer)?;
+        let image = screen.pack(&data.image)?;
+        screen.set_contents(&buffer, &image);
+        
+        Ok(screen)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Screen {
+    fn rows(&self, data: &[u8]) -> Vec<String> {
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let (byte, bit) = self.get_index(x as isize, y as isize);
+                        if (data[byte] >> bit) & 1 != 0 { '#' } else { '.' }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    
+    fn pack(&self, rows: &[String]) -> Result<Vec<u8>, String> {
+        if rows.len() != self.height {
+            return Err(format!("Expected {} pixel rows, got {}", self.height, rows.len()));
+        }
+        
+        let mut data = vec![0; self.image.len()];
+        
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != self.width {
+                return Err(format!("Expected {} pixels in row {}, got {}", self.width, y, row.chars().count()));
+            }
+            
+            for (x, pixel) in row.chars().enumerate() {
+                let (byte, bit) = self.get_index(x as isize, y as isize);
+                
+                match pixel {
+                    '#' => data[byte] |= 1 << bit,
+                    '.' => (),
+                    _ => return Err(format!("Invalid pixel '{}' in row {}, expected '#' or '.'", pixel, y))
+                }
+            }
+        }
+        
+        Ok(data)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use serde_test::{assert_de_tokens, assert_de_tokens_error, assert_ser_tokens, Token};
+    use std::fmt::{Debug, Formatter};
+
+    #[derive(serde::Deserialize)]
+    #[serde(transparent)]
+    struct Pixels(Screen);
+
+    impl PartialEq for Pixels {
+        fn eq(&self, other: &Self) -> bool {
+            (self.0.x, self.0.y, self.0.width, self.0.height) == (other.0.x, other.0.y, other.0.width, other.0.height)
+                && self.0.buffer == other.0.buffer
+                && self.0.image == other.0.image
+        }
+    }
+
+    impl Debug for Pixels {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?} / {:?}", self.0.rows(&self.0.buffer), self.0.rows(&self.0.image))
+        }
+    }
+
+    fn screen() -> Screen {
+        let mut screen = Screen::new(3, 2);
+        screen.x = 2;
+        screen.y = 1;
+        screen.set_pix(true);
+        screen.push_buffer();
+        screen.set_buffer_pixel(0, 0, true);
+
+        screen
+    }
+
+    fn tokens(width: u64, buffer: [&'static str; 2], image: [&'static str; 2]) -> Vec<Token> {
+        vec![
+            Token::Struct { name: "ScreenData", len: 6 },
+            Token::Str("x"),
+            Token::I64(2),
+            Token::Str("y"),
+            Token::I64(1),
+            Token::Str("width"),
+            Token::U64(width),
+            Token::Str("height"),
+            Token::U64(2),
+            Token::Str("buffer"),
+            Token::Seq { len: Some(2) },
+            Token::String(buffer[0]),
+            Token::String(buffer[1]),
+            Token::SeqEnd,
+            Token::Str("image"),
+            Token::Seq { len: Some(2) },
+            Token::String(image[0]),
+            Token::String(image[1]),
+            Token::SeqEnd,
+            Token::StructEnd
+        ]
+    }
+
+    #[test]
+    fn round_trips_pixel_rows() {
+        let tokens = tokens(3, ["#..", "..#"], ["...", "..#"]);
+
+        assert_ser_tokens(&screen(), &tokens);
+        assert_de_tokens(&Pixels(screen()), &tokens);
+    }
+
+    #[test]
+    fn rejects_malformed_pixel_rows() {
+        assert_de_tokens_error::<Pixels>(&tokens(3, ["#..", "..#"], ["...", "..X"]), "Invalid pixel 'X' in row 1, expected '#' or '.'");
+        assert_de_tokens_error::<Pixels>(&tokens(3, ["#..", ".#"], ["...", "..#"]), "Expected 3 pixels in row 1, got 2");
+        assert_de_tokens_error::<Pixels>(&tokens(257, ["#..", "..#"], ["...", "..#"]), &ConfigError::ScreenSize(257, 2).to_string());
+    }
 }
\ No newline at end of file
@@ -1,50 +1,137 @@
+use crate::machine::config::ConfigError;
 use batpu_assembly::components::address;
+use std::fmt::{Display, Formatter};
 
+pub const MAX_SIZE: u32 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFault {
+    AddressOutOfRange(u32),
+    Overflow(u32),
+    Underflow
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackPolicy {
+    Wrap,
+    Trap,
+    Grow
+}
+
+impl Display for StackFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackFault::AddressOutOfRange(address) => {
+                write!(f, "Address {} out of range, expected 0-{}", address, address::MAX_VALUE)
+            },
+            StackFault::Overflow(max_size) => write!(f, "Stack overflow, already holding {} entries", max_size),
+            StackFault::Underflow => write!(f, "Stack underflow, nothing to return to")
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "StackData", try_from = "StackData"))]
 pub struct Stack {
     max_size: u32,
+    policy: StackPolicy,
     
     stack: Vec<u32>,
+    high_water_mark: usize,
     stack_updated: bool
 }
 
 impl Stack {
     pub fn new(max_size: u32) -> Self {
+        Self::with_policy(max_size, StackPolicy::Wrap)
+    }
+    
+    pub fn with_policy(max_size: u32, policy: StackPolicy) -> Self {
         Self {
             max_size,
+            policy,
             
             stack: Vec::with_capacity(max_size as usize),
+            high_water_mark: 0,
             stack_updated: true
         }
     }
     
-    pub fn push(&mut self, address: u32) -> bool {
+    pub fn check_size(max_size: u32, policy: StackPolicy) -> Result<(), ConfigError> {
+        if (max_size == 0 && policy != StackPolicy::Grow) || max_size > MAX_SIZE {
+            return Err(ConfigError::StackSize(max_size));
+        }
+        
+        Ok(())
+    }
+    
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+    
+    pub fn policy(&self) -> StackPolicy {
+        self.policy
+    }
+    
+    pub fn set_policy(&mut self, policy: StackPolicy) {
+        self.policy = policy;
+    }
+    
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+    
+    pub fn reset_high_water_mark(&mut self) {
+        self.high_water_mark = self.stack.len();
+    }
+    
+    pub(crate) fn set_high_water_mark(&mut self, high_water_mark: usize) {
+        self.high_water_mark = high_water_mark;
+    }
+    
+    pub fn push(&mut self, address: u32) -> Result<(), StackFault> {
         if address > address::MAX_VALUE {
-            panic!("Address {} out of range, expected 0-{}", address, address::MAX_VALUE);
+            return Err(StackFault::AddressOutOfRange(address));
         }
         
-        if self.stack.len() as u32 == self.max_size {
-            self.stack.remove(0);
+        if self.stack.len() >= self.max_size as usize {
+            match self.policy {
+                StackPolicy::Wrap => {
+                    if self.stack.is_empty() {
+                        return Ok(());
+                    }
+                    
+                    self.stack.remove(0);
+                },
+                StackPolicy::Trap => return Err(StackFault::Overflow(self.max_size)),
+                StackPolicy::Grow => {}
+            }
         }
         
         self.stack.push(address);
+        self.high_water_mark = self.high_water_mark.max(self.stack.len());
         self.stack_updated = true;
         
-        true
+        Ok(())
     }
     
-    pub fn pop(&mut self) -> u32 {
+    pub fn pop(&mut self) -> Result<u32, StackFault> {
         let result = self.stack.pop();
         match result {
             Some(value) => {
                 self.stack_updated = true;
-                value
+                Ok(value)
             },
-            None => 0
+            None if self.policy == StackPolicy::Trap => Err(StackFault::Underflow),
+            None => Ok(0)
         }
     }
     
     pub fn clear(&mut self) {
         self.stack.clear();
+        self.high_water_mark = 0;
         self.stack_updated = true;
     }
     
@@ -52,6 +139,19 @@ impl Stack {
         &self.stack
     }
 
+    pub(crate) fn remap(&mut self, mut remap: impl FnMut(u32) -> u32) {
+        for address in &mut self.stack {
+            *address = remap(*address);
+        }
+        
+        self.stack_updated = true;
+    }
+
+    pub(crate) fn restore(&mut self, saved: Stack) {
+        *self = saved;
+        self.stack_updated = true;
+    }
+
     pub fn stack_updated(&self) -> bool {
         self.stack_updated
     }
@@ -59,4 +159,154 @@ impl Stack {
     pub fn disable_stack_updated(&mut self) {
         self.stack_updated = false;
     }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StackData {
+    max_size: u32,
+    policy: StackPolicy,
+    
+    stack: Vec<u32>,
+    high_water_mark: usize
+}
+
+#[cfg(feature = "serde")]
+impl From<Stack> for StackData {
+    fn from(stack: Stack) -> Self {
+        Self {
+            max_size: stack.max_size,
+            policy: stack.policy,
+            
+            stack: stack.stack,
+            high_water_mark: stack.high_water_mark
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<StackData> for Stack {
+    type Error = String;
+
+    fn try_from(data: StackData) -> Result<Self, Self::Error> {
+        Stack::check_size(data.max_size, data.policy).map_err(|error| error.to_string())?;
+        
+        if data.stack.len() > data.max_size as usize && data.policy != StackPolicy::Grow {
+            return Err(format!("Stack holds {} entries, expected at most {}", data.stack.len(), data.max_size));
+        }
+        
+        if data.high_water_mark < data.stack.len() {
+            return Err(format!("Stack high water mark {} is below its length {}", data.high_water_mark, data.stack.len()));
+        }
+        
+        let mut stack = Stack::with_policy(data.max_size, data.policy);
+        for address in data.stack {
+            stack.push(address).map_err(|fault| fault.to_string())?;
+        }
+        stack.high_water_mark = data.high_water_mark;
+        
+        Ok(stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "serde")]
+    use serde_test::{assert_de_tokens_error, assert_ser_tokens, Token};
+
+    fn full(policy: StackPolicy) -> Stack {
+        let mut stack = Stack::with_policy(2, policy);
+        stack.push(1).unwrap();
+        stack.push(2).unwrap();
+
+        stack
+    }
+
+    #[test]
+    fn wrap_drops_the_oldest_entry_when_full() {
+        let mut stack = full(StackPolicy::Wrap);
+
+        assert_eq!(stack.push(3), Ok(()));
+        assert_eq!(stack.stack(), &[2, 3]);
+        assert_eq!(stack.high_water_mark(), 2);
+    }
+
+    #[test]
+    fn trap_rejects_pushes_when_full() {
+        let mut stack = full(StackPolicy::Trap);
+
+        assert_eq!(stack.push(3), Err(StackFault::Overflow(2)));
+        assert_eq!(stack.stack(), &[1, 2]);
+    }
+
+    #[test]
+    fn grow_keeps_pushing_past_the_size() {
+        let mut stack = full(StackPolicy::Grow);
+
+        assert_eq!(stack.push(3), Ok(()));
+        assert_eq!(stack.stack(), &[1, 2, 3]);
+        assert_eq!(stack.high_water_mark(), 3);
+    }
+
+    #[test]
+    fn only_trap_faults_when_empty() {
+        for policy in [StackPolicy::Wrap, StackPolicy::Grow] {
+            let mut stack = full(policy);
+            assert_eq!(stack.pop(), Ok(2));
+            assert_eq!(stack.pop(), Ok(1));
+            assert_eq!(stack.pop(), Ok(0));
+        }
+
+        let mut stack = full(StackPolicy::Trap);
+        assert_eq!(stack.pop(), Ok(2));
+        assert_eq!(stack.pop(), Ok(1));
+        assert_eq!(stack.pop(), Err(StackFault::Underflow));
+    }
+
+    #[test]
+    fn rejects_addresses_out_of_range() {
+        let mut stack = Stack::with_policy(2, StackPolicy::Grow);
+
+        assert_eq!(stack.push(address::MAX_VALUE), Ok(()));
+        assert_eq!(stack.push(address::MAX_VALUE + 1), Err(StackFault::AddressOutOfRange(address::MAX_VALUE + 1)));
+        assert_eq!(stack.stack(), &[address::MAX_VALUE]);
+    }
+
+    #[cfg(feature = "serde")]
+    fn tokens(max_size: u32, policy: &'static str, stack: &[u32], high_water_mark: u64) -> Vec<Token> {
+        let mut tokens = vec![
+            Token::Struct { name: "StackData", len: 4 },
+            Token::Str("max_size"),
+            Token::U32(max_size),
+            Token::Str("policy"),
+            Token::UnitVariant { name: "StackPolicy", variant: policy },
+            Token::Str("stack"),
+            Token::Seq { len: Some(stack.len()) }
+        ];
+        tokens.extend(stack.iter().map(|&address| Token::U32(address)));
+        tokens.extend([Token::SeqEnd, Token::Str("high_water_mark"), Token::U64(high_water_mark), Token::StructEnd]);
+
+        tokens
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_the_stack() {
+        let mut stack = Stack::with_policy(2, StackPolicy::Trap);
+        stack.push(5).unwrap();
+        stack.push(6).unwrap();
+        stack.pop().unwrap();
+
+        assert_ser_tokens(&stack, &tokens(2, "Trap", &[5], 2));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rejects_invalid_stacks() {
+        assert_de_tokens_error::<Stack>(&tokens(1, "Trap", &[5, 6], 2), "Stack holds 2 entries, expected at most 1");
+        assert_de_tokens_error::<Stack>(&tokens(2, "Wrap", &[5, 6], 1), "Stack high water mark 1 is below its length 2");
+        assert_de_tokens_error::<Stack>(&tokens(2, "Wrap", &[1024], 1), "Address 1024 out of range, expected 0-1023");
+        assert_de_tokens_error::<Stack>(&tokens(0, "Wrap", &[], 0), &ConfigError::StackSize(0).to_string());
+    }
 }
\ No newline at end of file
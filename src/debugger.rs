@@ -0,0 +1,252 @@
+pub mod expression;
+
+use crate::debugger::expression::{Context, Expression};
+use crate::machine::Word;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Watchpoint {
+    MemoryRead(usize),
+    MemoryWrite(usize),
+    Register(usize),
+    PortRead(usize),
+    PortWrite(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub address: u32,
+    pub old: Option<Word>,
+    pub new: Word
+}
+
+pub struct Breakpoint {
+    pub condition: Option<Expression>,
+    pub ignore_count: u64,
+    
+    hit_count: u64
+}
+
+impl Breakpoint {
+    pub fn new() -> Self {
+        Self {
+            condition: None,
+            ignore_count: 0,
+            
+            hit_count: 0
+        }
+    }
+    
+    pub fn with_condition(condition: Expression) -> Self {
+        Self {
+            condition: Some(condition),
+            ..Self::new()
+        }
+    }
+    
+    pub fn hit_count(&self) -> u64 {
+        self.hit_count
+    }
+    
+    pub fn reset_hit_count(&mut self) {
+        self.hit_count = 0;
+    }
+    
+    fn condition_met(&self, context: &Context) -> bool {
+        self.condition.as_ref().is_none_or(|condition| condition.is_true(context))
+    }
+    
+    fn hit(&mut self) -> bool {
+        self.hit_count += 1;
+        self.hit_count > self.ignore_count
+    }
+}
+
+pub struct Debugger {
+    breakpoints: BTreeMap<u32, Breakpoint>,
+    watchpoints: BTreeSet<Watchpoint>,
+    
+    hits: Vec<WatchHit>,
+    resume_address: Option<u32>
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeSet::new(),
+            
+            hits: Vec::new(),
+            resume_address: None
+        }
+    }
+
+    pub fn breakpoints(&self) -> &BTreeMap<u32, Breakpoint> {
+        &self.breakpoints
+    }
+    
+    pub fn breakpoint(&self, address: u32) -> Option<&Breakpoint> {
+        self.breakpoints.get(&address)
+    }
+    
+    pub fn breakpoint_mut(&mut self, address: u32) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&address)
+    }
+
+    pub fn add_breakpoint(&mut self, address: u32) -> bool {
+        if self.breakpoints.contains_key(&address) {
+            return false;
+        }
+        
+        self.breakpoints.insert(address, Breakpoint::new());
+        true
+    }
+    
+    pub fn set_breakpoint(&mut self, address: u32, breakpoint: Breakpoint) -> Option<Breakpoint> {
+        self.breakpoints.insert(address, breakpoint)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u32) -> Option<Breakpoint> {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &BTreeSet<Watchpoint> {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.watchpoints.insert(watchpoint)
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.watchpoints.remove(&watchpoint)
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+    
+    pub(crate) fn armed(&mut self, address: u32) -> bool {
+        if self.resume_address.take() == Some(address) {
+            return false;
+        }
+        
+        self.breakpoints.contains_key(&address)
+    }
+    
+    pub(crate) fn remap_resume_address(&mut self, remap: impl FnOnce(u32) -> Option<u32>) {
+        self.resume_address = self.resume_address.and_then(remap);
+    }
+    
+    pub(crate) fn condition_met(&self, address: u32, context: &Context) -> bool {
+        self.breakpoints.get(&address).is_some_and(|breakpoint| breakpoint.condition_met(context))
+    }
+    
+    pub(crate) fn hit_breakpoint(&mut self, address: u32) -> bool {
+        let breakpoint = match self.breakpoints.get_mut(&address) {
+            Some(breakpoint) => breakpoint,
+            None => return false
+        };
+        
+        if !breakpoint.hit() {
+            return false;
+        }
+        
+        self.resume_address = Some(address);
+        true
+    }
+
+    pub(crate) fn watch(&mut self, watchpoint: Watchpoint, address: u32, old: Option<Word>, new: Word) {
+        if !self.watchpoints.contains(&watchpoint) {
+            return;
+        }
+        
+        self.hits.push(WatchHit {
+            watchpoint,
+            address,
+            old,
+            new
+        });
+    }
+    
+    pub(crate) fn take_hits(&mut self) -> Option<Vec<WatchHit>> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        
+        Some(std::mem::take(&mut self.hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::StepOutcome;
+    use crate::test_support::{ldi, machine, port, store};
+    use batpu_assembly::components::address::Address;
+    use batpu_assembly::components::location::Location;
+    use batpu_assembly::instruction::Instruction;
+
+    fn hits(outcome: StepOutcome) -> Vec<WatchHit> {
+        match outcome {
+            StepOutcome::Watchpoint { hits, .. } => hits,
+            outcome => panic!("Expected a watchpoint, got {:?}", outcome)
+        }
+    }
+
+    #[test]
+    fn register_watchpoints_fire_on_every_write() {
+        let mut machine = machine(vec![ldi(1, 5), ldi(1, 5)]);
+        machine.debugger_mut().add_watchpoint(Watchpoint::Register(1));
+
+        assert_eq!(hits(machine.tick().unwrap())[0].new, 5);
+        assert_eq!(hits(machine.tick().unwrap())[0].old, Some(5));
+    }
+
+    #[test]
+    fn memory_write_watchpoints_fire_on_every_write() {
+        let mut machine = machine(vec![ldi(1, 3), ldi(2, 7), store(1, 2), store(1, 2)]);
+        machine.debugger_mut().add_watchpoint(Watchpoint::MemoryWrite(3));
+
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+
+        assert_eq!(hits(machine.tick().unwrap())[0].old, Some(0));
+        assert_eq!(hits(machine.tick().unwrap())[0].old, Some(7));
+    }
+
+    #[test]
+    fn watch_hits_keep_the_pushed_frame() {
+        let mut machine = machine(vec![ldi(1, port(5)), store(1, 0)]);
+        machine.debugger_mut().add_watchpoint(Watchpoint::PortWrite(5));
+
+        machine.tick().unwrap();
+        match machine.tick().unwrap() {
+            StepOutcome::Watchpoint { hits, frame_pushed } => {
+                assert_eq!(hits.len(), 1);
+                assert!(frame_pushed);
+            },
+            outcome => panic!("Expected a watchpoint, got {:?}", outcome)
+        }
+    }
+
+    #[test]
+    fn breakpoints_honour_ignore_counts() {
+        let mut machine = machine(vec![Instruction::NoOperation, Instruction::Jump(Location::Address(Address::new(0)))]);
+
+        let mut breakpoint = Breakpoint::new();
+        breakpoint.ignore_count = 1;
+        machine.debugger_mut().set_breakpoint(0, breakpoint);
+
+        assert_eq!(machine.tick().unwrap(), StepOutcome::Executed);
+        assert_eq!(machine.tick().unwrap(), StepOutcome::Executed);
+        assert_eq!(machine.tick().unwrap(), StepOutcome::Breakpoint(0));
+        assert_eq!(machine.tick().unwrap(), StepOutcome::Executed);
+        assert_eq!(machine.debugger().breakpoint(0).unwrap().hit_count(), 2);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,545 @@
+use crate::machine::{Machine, Word, REGISTER_COUNT};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub struct Context<'a> {
+    pub program_counter: u32,
+    pub registers: &'a [Word],
+    pub memory: &'a [Word],
+    pub zero_flag: bool,
+    pub carry_flag: bool,
+    pub stack: &'a [u32]
+}
+
+impl<'a> Context<'a> {
+    pub fn new(machine: &'a Machine) -> Self {
+        Self {
+            program_counter: machine.program_counter(),
+            registers: machine.registers(),
+            memory: machine.memory(),
+            zero_flag: machine.zero_flag(),
+            carry_flag: machine.carry_flag(),
+            stack: machine.stack().stack()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+    Complement
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i64),
+    Register(usize),
+    ProgramCounter,
+    ZeroFlag,
+    CarryFlag,
+    StackDepth,
+    Memory(Box<Expression>),
+    Stack(Box<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpressionError {
+    pub position: usize,
+    pub message: String
+}
+
+impl ExpressionError {
+    fn new(position: usize, message: String) -> Self {
+        Self {
+            position,
+            message
+        }
+    }
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ExpressionError {}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            index: 0,
+            length: source.len()
+        };
+        
+        let expression = parser.parse_binary(0)?;
+        if parser.index < parser.tokens.len() {
+            return Err(ExpressionError::new(parser.position(), "Unexpected token".to_string()));
+        }
+        
+        Ok(expression)
+    }
+    
+    pub fn evaluate(&self, context: &Context) -> i64 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Register(register) => context.registers.get(*register).map_or(0, |&value| value as i64),
+            Expression::ProgramCounter => context.program_counter as i64,
+            Expression::ZeroFlag => context.zero_flag as i64,
+            Expression::CarryFlag => context.carry_flag as i64,
+            Expression::StackDepth => context.stack.len() as i64,
+            Expression::Memory(address) => {
+                let address = address.evaluate(context);
+                if address < 0 {
+                    return 0;
+                }
+                
+                context.memory.get(address as usize).map_or(0, |&value| value as i64)
+            },
+            Expression::Stack(depth) => {
+                let depth = depth.evaluate(context);
+                if depth < 0 || depth as usize >= context.stack.len() {
+                    return 0;
+                }
+                
+                context.stack[context.stack.len() - 1 - depth as usize] as i64
+            },
+            Expression::Unary(operator, operand) => {
+                let operand = operand.evaluate(context);
+                match operator {
+                    UnaryOperator::Not => (operand == 0) as i64,
+                    UnaryOperator::Negate => operand.wrapping_neg(),
+                    UnaryOperator::Complement => !operand
+                }
+            },
+            Expression::Binary(BinaryOperator::Or, left, right) => {
+                (left.is_true(context) || right.is_true(context)) as i64
+            },
+            Expression::Binary(BinaryOperator::And, left, right) => {
+                (left.is_true(context) && right.is_true(context)) as i64
+            },
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(context);
+                let right = right.evaluate(context);
+                
+                match operator {
+                    BinaryOperator::Equal => (left == right) as i64,
+                    BinaryOperator::NotEqual => (left != right) as i64,
+                    BinaryOperator::Less => (left < right) as i64,
+                    BinaryOperator::LessEqual => (left <= right) as i64,
+                    BinaryOperator::Greater => (left > right) as i64,
+                    BinaryOperator::GreaterEqual => (left >= right) as i64,
+                    BinaryOperator::BitOr => left | right,
+                    BinaryOperator::BitXor => left ^ right,
+                    BinaryOperator::BitAnd => left & right,
+                    BinaryOperator::Add => left.wrapping_add(right),
+                    BinaryOperator::Subtract => left.wrapping_sub(right),
+                    BinaryOperator::Multiply => left.wrapping_mul(right),
+                    BinaryOperator::Divide => left.checked_div(right).unwrap_or(0),
+                    BinaryOperator::Remainder => left.checked_rem(right).unwrap_or(0),
+                    BinaryOperator::Or | BinaryOperator::And => unreachable!()
+                }
+            }
+        }
+    }
+    
+    pub fn is_true(&self, context: &Context) -> bool {
+        self.evaluate(context) != 0
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ExpressionError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Expression::parse(source)
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Not => "!",
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Complement => "~"
+        })
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Or => "||",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%"
+        })
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Number(value) => write!(f, "{}", value),
+            Expression::Register(register) => write!(f, "r{}", register),
+            Expression::ProgramCounter => f.write_str("pc"),
+            Expression::ZeroFlag => f.write_str("zero"),
+            Expression::CarryFlag => f.write_str("carry"),
+            Expression::StackDepth => f.write_str("sp"),
+            Expression::Memory(address) => write!(f, "mem[{}]", address),
+            Expression::Stack(depth) => write!(f, "stack[{}]", depth),
+            Expression::Unary(operator, operand) => match operand.as_ref() {
+                Expression::Binary(..) => write!(f, "{}({})", operator, operand),
+                _ => write!(f, "{}{}", operator, operand)
+            },
+            Expression::Binary(operator, left, right) => {
+                match left.as_ref() {
+                    Expression::Binary(..) => write!(f, "({})", left)?,
+                    _ => write!(f, "{}", left)?
+                }
+                
+                write!(f, " {} ", operator)?;
+                
+                match right.as_ref() {
+                    Expression::Binary(..) => write!(f, "({})", right),
+                    _ => write!(f, "{}", right)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(&'static str)
+}
+
+const SYMBOLS: &[&str] = &["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "[", "]"];
+
+const LEVELS: &[&[(&str, BinaryOperator)]] = &[
+    &[("||", BinaryOperator::Or)],
+    &[("&&", BinaryOperator::And)],
+    &[
+        ("==", BinaryOperator::Equal),
+        ("!=", BinaryOperator::NotEqual),
+        ("<=", BinaryOperator::LessEqual),
+        (">=", BinaryOperator::GreaterEqual),
+        ("<", BinaryOperator::Less),
+        (">", BinaryOperator::Greater)
+    ],
+    &[("|", BinaryOperator::BitOr)],
+    &[("^", BinaryOperator::BitXor)],
+    &[("&", BinaryOperator::BitAnd)],
+    &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)],
+    &[("*", BinaryOperator::Multiply), ("/", BinaryOperator::Divide), ("%", BinaryOperator::Remainder)]
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    
+    while let Some(character) = source[position..].chars().next() {
+        let rest = &source[position..];
+        
+        if character.is_whitespace() {
+            position += character.len_utf8();
+            continue;
+        }
+        
+        if character.is_ascii_alphanumeric() || character == '_' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            
+            let text = rest[..length].to_ascii_lowercase();
+            
+            if character.is_ascii_digit() {
+                let value = parse_number(&text.replace('_', ""))
+                    .ok_or_else(|| ExpressionError::new(position, format!("Invalid number \"{}\"", &rest[..length])))?;
+                
+                tokens.push((position, Token::Number(value)));
+            } else {
+                tokens.push((position, Token::Identifier(text)));
+            }
+            
+            position += length;
+            continue;
+        }
+        
+        match SYMBOLS.iter().find(|&&symbol| rest.starts_with(symbol)) {
+            Some(&symbol) => {
+                tokens.push((position, Token::Symbol(symbol)));
+                position += symbol.len();
+            },
+            None => {
+                return Err(ExpressionError::new(position, format!("Unexpected character '{}'", character)));
+            }
+        }
+    }
+    
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    if let Some(hexadecimal) = text.strip_prefix("0x") {
+        i64::from_str_radix(hexadecimal, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    length: usize
+}
+
+impl Parser {
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map_or(self.length, |(position, _)| *position)
+    }
+    
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.tokens.get(self.index) {
+            Some((_, Token::Symbol(found))) if *found == symbol => {
+                self.index += 1;
+                true
+            },
+            _ => false
+        }
+    }
+    
+    fn expect(&mut self, symbol: &str) -> Result<(), ExpressionError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(ExpressionError::new(self.position(), format!("Expected '{}'", symbol)))
+        }
+    }
+    
+    fn parse_binary(&mut self, level: usize) -> Result<Expression, ExpressionError> {
+        if level == LEVELS.len() {
+            return self.parse_unary();
+        }
+        
+        let mut left = self.parse_binary(level + 1)?;
+        
+        'operators: loop {
+            for &(symbol, operator) in LEVELS[level] {
+                if self.eat(symbol) {
+                    let right = self.parse_binary(level + 1)?;
+                    left = Expression::Binary(operator, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            
+            return Ok(left);
+        }
+    }
+    
+    fn parse_unary(&mut self) -> Result<Expression, ExpressionError> {
+        let operator = if self.eat("!") {
+            UnaryOperator::Not
+        } else if self.eat("-") {
+            UnaryOperator::Negate
+        } else if self.eat("~") {
+            UnaryOperator::Complement
+        } else {
+            return self.parse_primary();
+        };
+        
+        Ok(Expression::Unary(operator, Box::new(self.parse_unary()?)))
+    }
+    
+    fn parse_primary(&mut self) -> Result<Expression, ExpressionError> {
+        let position = self.position();
+        
+        let token = match self.tokens.get(self.index) {
+            Some((_, token)) => token.clone(),
+            None => return Err(ExpressionError::new(position, "Unexpected end of expression".to_string()))
+        };
+        
+        self.index += 1;
+        
+        match token {
+            Token::Number(value) => Ok(Expression::Number(value)),
+            Token::Symbol("(") => {
+                let expression = self.parse_binary(0)?;
+                self.expect(")")?;
+                
+                Ok(expression)
+            },
+            Token::Symbol(symbol) => Err(ExpressionError::new(position, format!("Unexpected '{}'", symbol))),
+            Token::Identifier(identifier) => match identifier.as_str() {
+                "pc" => Ok(Expression::ProgramCounter),
+                "zero" | "z" => Ok(Expression::ZeroFlag),
+                "carry" | "c" => Ok(Expression::CarryFlag),
+                "sp" => Ok(Expression::StackDepth),
+                "true" => Ok(Expression::Number(1)),
+                "false" => Ok(Expression::Number(0)),
+                "mem" | "stack" => {
+                    self.expect("[")?;
+                    let index = Box::new(self.parse_binary(0)?);
+                    self.expect("]")?;
+                    
+                    if identifier == "mem" {
+                        Ok(Expression::Memory(index))
+                    } else {
+                        Ok(Expression::Stack(index))
+                    }
+                },
+                _ => match identifier.strip_prefix('r').and_then(|register| register.parse::<usize>().ok()) {
+                    Some(register) if register < REGISTER_COUNT => Ok(Expression::Register(register)),
+                    _ => Err(ExpressionError::new(position, format!("Unknown identifier \"{}\"", identifier)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGISTERS: [Word; REGISTER_COUNT] = [0, 1, 2, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255];
+
+    fn evaluate(source: &str) -> i64 {
+        let mut memory = [0; 0x40];
+        memory[0x20] = 11;
+        memory[3] = 42;
+
+        let context = Context {
+            program_counter: 12,
+            registers: &REGISTERS,
+            memory: &memory,
+            zero_flag: true,
+            carry_flag: false,
+            stack: &[5, 9]
+        };
+
+        Expression::parse(source).unwrap().evaluate(&context)
+    }
+
+    fn error(source: &str) -> ExpressionError {
+        Expression::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn binds_tighter_operators_first() {
+        assert_eq!(evaluate("2 + 3 * 4"), 14);
+        assert_eq!(evaluate("1 + 2 == 3"), 1);
+        assert_eq!(evaluate("4 | 6 ^ 3 & 5"), 7);
+        assert_eq!(evaluate("0 && 1 || 1"), 1);
+        assert_eq!(evaluate("1 || 0 && 0"), 1);
+    }
+
+    #[test]
+    fn associates_to_the_left() {
+        assert_eq!(evaluate("10 - 3 - 2"), 5);
+        assert_eq!(evaluate("100 / 10 / 5"), 2);
+        assert_eq!(evaluate("17 % 10 % 4"), 3);
+    }
+
+    #[test]
+    fn applies_unary_operators() {
+        assert_eq!(evaluate("-3 + 5"), 2);
+        assert_eq!(evaluate("- -3"), 3);
+        assert_eq!(evaluate("-(1 + 2)"), -3);
+        assert_eq!(evaluate("!0"), 1);
+        assert_eq!(evaluate("~0"), -1);
+        assert_eq!(evaluate("2 - -2"), 4);
+    }
+
+    #[test]
+    fn groups_with_parentheses() {
+        assert_eq!(evaluate("(2 + 3) * 4"), 20);
+        assert_eq!(evaluate("10 - (3 - 2)"), 9);
+        assert_eq!(evaluate("((1))"), 1);
+    }
+
+    #[test]
+    fn reads_machine_state() {
+        assert_eq!(evaluate("r3"), 7);
+        assert_eq!(evaluate("r15 + 1"), 256);
+        assert_eq!(evaluate("mem[0x20]"), 11);
+        assert_eq!(evaluate("mem[r1 + r2]"), 42);
+        assert_eq!(evaluate("mem[-1]"), 0);
+        assert_eq!(evaluate("mem[0x1000]"), 0);
+        assert_eq!(evaluate("pc"), 12);
+        assert_eq!(evaluate("zero && !carry"), 1);
+        assert_eq!(evaluate("sp"), 2);
+        assert_eq!(evaluate("stack[0]"), 9);
+        assert_eq!(evaluate("stack[1]"), 5);
+        assert_eq!(evaluate("stack[2]"), 0);
+        assert_eq!(evaluate("r3 == 7 && mem[0x20] > 10 && zero"), 1);
+    }
+
+    #[test]
+    fn divides_by_zero_to_zero() {
+        assert_eq!(evaluate("5 / 0"), 0);
+        assert_eq!(evaluate("5 % 0"), 0);
+        assert_eq!(evaluate("5 / r0"), 0);
+    }
+
+    #[test]
+    fn reports_errors_with_positions() {
+        assert_eq!(error("r3 =="), ExpressionError::new(5, "Unexpected end of expression".to_string()));
+        assert_eq!(error("r16 == 1"), ExpressionError::new(0, "Unknown identifier \"r16\"".to_string()));
+        assert_eq!(error("1 $ 2"), ExpressionError::new(2, "Unexpected character '$'".to_string()));
+        assert_eq!(error("(1 + 2"), ExpressionError::new(6, "Expected ')'".to_string()));
+        assert_eq!(error("mem[1"), ExpressionError::new(5, "Expected ']'".to_string()));
+        assert_eq!(error("mem 1"), ExpressionError::new(4, "Expected '['".to_string()));
+        assert_eq!(error("1 2"), ExpressionError::new(2, "Unexpected token".to_string()));
+        assert_eq!(error("0xZZ"), ExpressionError::new(0, "Invalid number \"0xZZ\"".to_string()));
+        assert_eq!(error("* 2"), ExpressionError::new(0, "Unexpected '*'".to_string()));
+    }
+
+    #[test]
+    fn displays_a_parsable_expression() {
+        for source in ["r3 == 7 && mem[0x20] > 10 && zero", "-(1 + 2) * 3", "10 - (3 - 2)", "!(r1 & 4)"] {
+            let expression = Expression::parse(source).unwrap();
+            assert_eq!(Expression::parse(&expression.to_string()).unwrap(), expression);
+        }
+    }
+}
\ No newline at end of file
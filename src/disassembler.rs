@@ -0,0 +1,379 @@
+use crate::bus::PORT_NAMES;
+use crate::linker::{link, resolve_label, symbol_name, LinkError, SymbolTable};
+use crate::machine::{Word, PORTS_ADDRESS, REGISTER_COUNT};
+use batpu_assembly::components::condition::Condition;
+use batpu_assembly::components::immediate;
+use batpu_assembly::components::location::Location;
+use batpu_assembly::instruction::Instruction;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+pub fn format_condition(condition: &Condition) -> &'static str {
+    match condition {
+        Condition::Zero     => "zero",
+        Condition::NotZero  => "notzero",
+        Condition::Carry    => "carry",
+        Condition::NotCarry => "notcarry"
+    }
+}
+
+pub fn format_location(location: &Location) -> String {
+    match location {
+        Location::Address(address) => address.address().to_string(),
+        Location::Offset(offset) => format!("{:+}", offset.offset()),
+        Location::Label(label) => format!(".{}", symbol_name(&label.to_string()))
+    }
+}
+
+pub fn format_instruction(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::NoOperation => "NOP".to_string(),
+        Instruction::Halt => "HLT".to_string(),
+        Instruction::Addition(a, b, c) => format!("ADD r{} r{} r{}", a.register(), b.register(), c.register()),
+        Instruction::Subtraction(a, b, c) => format!("SUB r{} r{} r{}", a.register(), b.register(), c.register()),
+        Instruction::BitwiseNOR(a, b, c) => format!("NOR r{} r{} r{}", a.register(), b.register(), c.register()),
+        Instruction::BitwiseAND(a, b, c) => format!("AND r{} r{} r{}", a.register(), b.register(), c.register()),
+        Instruction::BitwiseXOR(a, b, c) => format!("XOR r{} r{} r{}", a.register(), b.register(), c.register()),
+        Instruction::RightShift(a, c) => format!("RSH r{} r{}", a.register(), c.register()),
+        Instruction::LoadImmediate(a, immediate) => format!("LDI r{} {}", a.register(), immediate.immediate()),
+        Instruction::AddImmediate(a, immediate) => format!("ADI r{} {}", a.register(), immediate.immediate()),
+        Instruction::Jump(location) => format!("JMP {}", format_location(location)),
+        Instruction::Branch(condition, location) => format!("BRH {} {}", format_condition(condition), format_location(location)),
+        Instruction::Call(location) => format!("CAL {}", format_location(location)),
+        Instruction::Return => "RET".to_string(),
+        Instruction::MemoryLoad(a, b, offset) => format_memory("LOD", a.register(), b.register(), offset.offset()),
+        Instruction::MemoryStore(a, b, offset) => format_memory("STR", a.register(), b.register(), offset.offset())
+    }
+}
+
+fn format_memory(mnemonic: &str, a: impl Display, b: impl Display, offset: i32) -> String {
+    if offset == 0 {
+        format!("{} r{} r{}", mnemonic, a, b)
+    } else {
+        format!("{} r{} r{} {}", mnemonic, a, b, offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disassembly {
+    pub text: String,
+    pub labels: SymbolTable
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoundTripError {
+    Assemble(String),
+    Link(Vec<LinkError>),
+    Length {
+        expected: usize,
+        found: usize
+    },
+    Mismatch {
+        index: usize,
+        expected: String,
+        found: String
+    }
+}
+
+impl Display for RoundTripError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoundTripError::Assemble(error) => write!(f, "Disassembly does not assemble: {}", error),
+            RoundTripError::Link(errors) => {
+                let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "Disassembly does not link: {}", errors.join("; "))
+            },
+            RoundTripError::Length { expected, found } => {
+                write!(f, "Disassembly assembles to {} instructions, expected {}", found, expected)
+            },
+            RoundTripError::Mismatch { index, expected, found } => {
+                write!(f, "Instruction {} assembles to \"{}\", expected \"{}\"", index, found, expected)
+            }
+        }
+    }
+}
+
+impl Error for RoundTripError {}
+
+pub fn disassemble(instructions: &[Instruction]) -> Disassembly {
+    disassemble_with_symbols(instructions, &SymbolTable::new())
+}
+
+pub fn disassemble_with_symbols(instructions: &[Instruction], symbols: &SymbolTable) -> Disassembly {
+    let names = label_names(instructions, symbols);
+    let mut text = String::new();
+
+    let mut known: [Option<Word>; REGISTER_COUNT] = [None; REGISTER_COUNT];
+    known[0] = Some(0);
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        if let Some(name) = names.get(&(index as u32)) {
+            text.push_str(&format!(".{}\n", name));
+            forget(&mut known);
+        }
+
+        let line = format_resolved(index, instruction, &names, symbols);
+        match port_comment(instruction, &known) {
+            Some(port) => text.push_str(&format!("  {:<24}// {}\n", line, port)),
+            None => text.push_str(&format!("  {}\n", line))
+        }
+
+        track(instruction, &mut known);
+    }
+
+    if let Some(name) = names.get(&(instructions.len() as u32)) {
+        text.push_str(&format!(".{}\n", name));
+    }
+
+    let labels = names
+        .into_iter()
+        .map(|(address, name)| (name, address))
+        .collect();
+
+    Disassembly {
+        text,
+        labels
+    }
+}
+
+pub fn verify_round_trip(instructions: &[Instruction]) -> Result<(), RoundTripError> {
+    let disassembly = disassemble(instructions);
+
+    let assembled = batpu_assembly::assemble(&disassembly.text)
+        .map_err(|error| RoundTripError::Assemble(error.to_string()))?;
+
+    let symbols = disassembly.labels;
+    let assembled = link(&assembled, &symbols).map_err(RoundTripError::Link)?;
+
+    if assembled.len() != instructions.len() {
+        return Err(RoundTripError::Length {
+            expected: instructions.len(),
+            found: assembled.len()
+        });
+    }
+
+    let no_names = BTreeMap::new();
+    for (index, (original, assembled)) in instructions.iter().zip(&assembled).enumerate() {
+        let expected = format_resolved(index, original, &no_names, &symbols);
+        let found = format_resolved(index, assembled, &no_names, &symbols);
+
+        if expected != found {
+            return Err(RoundTripError::Mismatch {
+                index,
+                expected,
+                found
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn target(index: usize, location: &Location, symbols: &SymbolTable) -> Option<u32> {
+    match location {
+        Location::Address(address) => Some(address.address()),
+        Location::Offset(offset) => u32::try_from(index as i64 + offset.offset() as i64).ok(),
+        Location::Label(label) => resolve_label(symbols, &label.to_string())
+    }
+}
+
+fn label_names(instructions: &[Instruction], symbols: &SymbolTable) -> BTreeMap<u32, String> {
+    let mut names = BTreeMap::new();
+    let length = instructions.len() as u32;
+
+    let mut sorted: Vec<(&String, &u32)> = symbols.iter().collect();
+    sorted.sort();
+
+    for (name, &address) in sorted {
+        if address <= length {
+            names.entry(address).or_insert_with(|| symbol_name(name).to_string());
+        }
+    }
+
+    let mut taken: HashSet<String> = symbols.keys().map(|name| symbol_name(name).to_string()).collect();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let (location, prefix) = match instruction {
+            Instruction::Call(location) => (location, "function"),
+            Instruction::Jump(location) | Instruction::Branch(_, location) => (location, "label"),
+            _ => continue
+        };
+
+        if let Some(address) = target(index, location, symbols)
+            && address <= length
+            && !names.contains_key(&address) {
+            let name = unique_name(&taken, format!("{}_{}", prefix, address));
+            taken.insert(name.clone());
+            names.insert(address, name);
+        }
+    }
+
+    names
+}
+
+fn unique_name(taken: &HashSet<String>, name: String) -> String {
+    if !taken.contains(&name) {
+        return name;
+    }
+
+    (2..)
+        .map(|suffix| format!("{}_{}", name, suffix))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+fn format_resolved(index: usize, instruction: &Instruction, names: &BTreeMap<u32, String>, symbols: &SymbolTable) -> String {
+    let location = match instruction {
+        Instruction::Jump(location) | Instruction::Branch(_, location) | Instruction::Call(location) => location,
+        _ => return format_instruction(instruction)
+    };
+
+    let location = match target(index, location, symbols) {
+        Some(address) => match names.get(&address) {
+            Some(name) => format!(".{}", name),
+            None => address.to_string()
+        },
+        None => format_location(location)
+    };
+
+    match instruction {
+        Instruction::Branch(condition, _) => format!("BRH {} {}", format_condition(condition), location),
+        Instruction::Call(_) => format!("CAL {}", location),
+        _ => format!("JMP {}", location)
+    }
+}
+
+fn port_comment(instruction: &Instruction, known: &[Option<Word>; REGISTER_COUNT]) -> Option<&'static str> {
+    let (a, offset) = match instruction {
+        Instruction::MemoryLoad(a, _, offset) | Instruction::MemoryStore(a, _, offset) => (a, offset),
+        _ => return None
+    };
+
+    let base = known[a.register() as usize]?;
+    let address = (base as i32 + offset.offset()).rem_euclid(immediate::MAX_POSSIBLE_COUNT as i32) as usize;
+
+    if address < PORTS_ADDRESS {
+        return None;
+    }
+
+    Some(PORT_NAMES[address - PORTS_ADDRESS])
+}
+
+fn track(instruction: &Instruction, known: &mut [Option<Word>; REGISTER_COUNT]) {
+    match instruction {
+        Instruction::LoadImmediate(a, immediate) => set_known(known, a.register() as usize, Some(immediate.immediate() as Word)),
+        Instruction::AddImmediate(a, immediate) => {
+            let register = a.register() as usize;
+            let value = known[register].map(|value| value.wrapping_add(immediate.immediate() as Word));
+
+            set_known(known, register, value);
+        },
+        Instruction::Addition(_, _, c)
+        | Instruction::Subtraction(_, _, c)
+        | Instruction::BitwiseNOR(_, _, c)
+        | Instruction::BitwiseAND(_, _, c)
+        | Instruction::BitwiseXOR(_, _, c)
+        | Instruction::RightShift(_, c) => set_known(known, c.register() as usize, None),
+        Instruction::MemoryLoad(_, b, _) => set_known(known, b.register() as usize, None),
+        Instruction::Jump(_) | Instruction::Call(_) | Instruction::Return | Instruction::Halt => forget(known),
+        _ => {}
+    }
+}
+
+fn set_known(known: &mut [Option<Word>; REGISTER_COUNT], register: usize, value: Option<Word>) {
+    if register != 0 {
+        known[register] = value;
+    }
+}
+
+fn forget(known: &mut [Option<Word>; REGISTER_COUNT]) {
+    known.fill(None);
+    known[0] = Some(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::r;
+    use batpu_assembly::components::address::Address;
+    use batpu_assembly::components::immediate::Immediate;
+    use batpu_assembly::components::offset::Offset;
+
+    fn to(address: u32) -> Location {
+        Location::Address(Address::new(address))
+    }
+
+    fn label(name: &str) -> Location {
+        Location::Label(name.into())
+    }
+
+    fn program() -> Vec<Instruction> {
+        vec![
+            Instruction::LoadImmediate(r(1), Immediate::new(PORTS_ADDRESS as i32)),
+            Instruction::LoadImmediate(r(2), Immediate::new(-3)),
+            Instruction::MemoryStore(r(1), r(2), Offset::new(0)),
+            Instruction::MemoryStore(r(1), r(0), Offset::new(2)),
+            Instruction::MemoryLoad(r(1), r(3), Offset::new(-1)),
+            Instruction::Call(to(9)),
+            Instruction::Branch(Condition::NotCarry, to(0)),
+            Instruction::Jump(Location::Offset(Offset::new(-2))),
+            Instruction::Halt,
+            Instruction::Addition(r(1), r(2), r(3)),
+            Instruction::RightShift(r(3), r(4)),
+            Instruction::Return
+        ]
+    }
+
+    #[test]
+    fn round_trips_a_program() {
+        assert_eq!(verify_round_trip(&program()), Ok(()));
+    }
+
+    #[test]
+    fn comments_ports_and_assembles_them() {
+        let disassembly = disassemble(&program());
+
+        assert!(disassembly.text.contains("  STR r1 r2               // pixel_x\n"));
+        assert!(disassembly.text.contains("  STR r1 r0 2             // draw_pixel\n"));
+        assert!(disassembly.text.contains("  LOD r1 r3 -1\n"));
+        assert!(batpu_assembly::assemble(&disassembly.text).is_ok());
+    }
+
+    #[test]
+    fn names_targets() {
+        let disassembly = disassemble(&program());
+
+        assert!(disassembly.text.starts_with(".label_0\n"));
+        assert!(disassembly.text.contains("  CAL .function_9\n"));
+        assert!(disassembly.text.contains(".label_5\n  CAL .function_9\n"));
+        assert!(disassembly.text.contains("  JMP .label_5\n"));
+        assert_eq!(disassembly.labels.get("function_9"), Some(&9));
+    }
+
+    #[test]
+    fn keeps_synthesized_names_unique() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(".label_1".to_string(), 0);
+        symbols.insert("label_1_2".to_string(), 2);
+
+        let instructions = [Instruction::Jump(to(1)), Instruction::Jump(label("label_1")), Instruction::Halt];
+        let disassembly = disassemble_with_symbols(&instructions, &symbols);
+
+        assert_eq!(disassembly.labels.get("label_1"), Some(&0));
+        assert_eq!(disassembly.labels.get("label_1_3"), Some(&1));
+        assert!(disassembly.text.contains(".label_1_3\n  JMP .label_1\n"));
+    }
+
+    #[test]
+    fn normalises_label_dots() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("start".to_string(), 1);
+
+        let instructions = [Instruction::Jump(label(".start")), Instruction::Jump(label("start"))];
+        let disassembly = disassemble_with_symbols(&instructions, &symbols);
+
+        assert_eq!(disassembly.text, "  JMP .start\n.start\n  JMP .start\n");
+        assert_eq!(format_location(&label("start")), format_location(&label(".start")));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+use crate::components::stack::StackFault;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MachineErrorKind {
+    UnresolvedLabel {
+        address: u32,
+        label: String
+    },
+    UnresolvedOffset {
+        address: u32,
+        offset: i32
+    },
+    StackFault {
+        address: u32,
+        fault: StackFault
+    },
+    ProgramCounterOutOfRange {
+        program_counter: u32,
+        length: usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineError {
+    pub kind: MachineErrorKind,
+    pub location: Option<String>
+}
+
+impl MachineError {
+    pub fn address(&self) -> u32 {
+        match &self.kind {
+            MachineErrorKind::UnresolvedLabel { address, .. } => *address,
+            MachineErrorKind::UnresolvedOffset { address, .. } => *address,
+            MachineErrorKind::StackFault { address, .. } => *address,
+            MachineErrorKind::ProgramCounterOutOfRange { program_counter, .. } => *program_counter
+        }
+    }
+}
+
+impl From<MachineErrorKind> for MachineError {
+    fn from(kind: MachineErrorKind) -> Self {
+        Self {
+            kind,
+            location: None
+        }
+    }
+}
+
+impl Display for MachineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            MachineErrorKind::UnresolvedLabel { address, label } => {
+                write!(f, "Unresolved label \"{}\" at address {}", label, address)?;
+            },
+            MachineErrorKind::UnresolvedOffset { address, offset } => {
+                write!(f, "Unresolved offset {} at address {}", offset, address)?;
+            },
+            MachineErrorKind::StackFault { address, fault } => {
+                write!(f, "Stack fault at address {}: {}", address, fault)?;
+            },
+            MachineErrorKind::ProgramCounterOutOfRange { program_counter, length } => {
+                write!(f, "Program counter {} is past the end of the program ({} instructions)", program_counter, length)?;
+            }
+        }
+
+        match &self.location {
+            Some(location) => write!(f, " ({})", location),
+            None => Ok(())
+        }
+    }
+}
+
+impl Error for MachineError {}
\ No newline at end of file
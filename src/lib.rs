@@ -1,2 +1,18 @@
+#![allow(clippy::new_without_default)]
+
 pub mod machine;
-pub mod components;
\ No newline at end of file
+pub mod components;
+pub mod error;
+pub mod linker;
+pub mod debugger;
+pub mod disassembler;
+pub mod trace;
+pub mod bus;
+pub mod source_map;
+pub mod machine_code;
+
+#[cfg(test)]
+mod test_support;
+
+#[cfg(feature = "tui")]
+pub mod tui;
\ No newline at end of file
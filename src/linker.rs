@@ -0,0 +1,234 @@
+use batpu_assembly::components::address::{self, Address};
+use batpu_assembly::components::location::Location;
+use batpu_assembly::instruction::Instruction;
+use batpu_assembly::InstructionVec;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+pub type SymbolTable = HashMap<String, u32>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkErrorKind {
+    UndefinedLabel(String),
+    TargetOutOfRange(i64),
+    TargetPastProgram(u32),
+    ProgramTooLong(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkError {
+    pub index: usize,
+    pub kind: LinkErrorKind
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            LinkErrorKind::UndefinedLabel(label) => {
+                write!(f, "Instruction {}: undefined label \"{}\"", self.index, label)
+            },
+            LinkErrorKind::TargetOutOfRange(target) => {
+                write!(f, "Instruction {}: target {} out of range, expected 0-{}", self.index, target, address::MAX_VALUE)
+            },
+            LinkErrorKind::TargetPastProgram(target) => {
+                write!(f, "Instruction {}: target {} is past the end of the program", self.index, target)
+            },
+            LinkErrorKind::ProgramTooLong(length) => {
+                write!(f, "Instruction {}: program has {} instructions, expected at most {}", self.index, length, address::MAX_POSSIBLE_COUNT)
+            }
+        }
+    }
+}
+
+impl Error for LinkError {}
+
+pub fn symbol_name(label: &str) -> &str {
+    label.trim_start_matches('.')
+}
+
+pub fn resolve_label(symbols: &SymbolTable, label: &str) -> Option<u32> {
+    symbols.get(symbol_name(label)).copied()
+}
+
+pub fn location(instruction: &Instruction) -> Option<&Location> {
+    match instruction {
+        Instruction::Jump(location) => Some(location),
+        Instruction::Branch(_, location) => Some(location),
+        Instruction::Call(location) => Some(location),
+        _ => None
+    }
+}
+
+pub fn location_mut(instruction: &mut Instruction) -> Option<&mut Location> {
+    match instruction {
+        Instruction::Jump(location) => Some(location),
+        Instruction::Branch(_, location) => Some(location),
+        Instruction::Call(location) => Some(location),
+        _ => None
+    }
+}
+
+pub fn link(instructions: &[Instruction], symbols: &SymbolTable) -> Result<InstructionVec, Vec<LinkError>> {
+    let mut linked = instructions.to_vec();
+    let mut errors = Vec::new();
+    
+    let length = linked.len();
+    let capacity = address::MAX_POSSIBLE_COUNT as usize;
+    if length > capacity {
+        errors.push(LinkError {
+            index: capacity,
+            kind: LinkErrorKind::ProgramTooLong(length)
+        });
+    }
+
+    for (index, instruction) in linked.iter_mut().enumerate() {
+        let location = match location_mut(instruction) {
+            Some(location) => location,
+            None => continue
+        };
+
+        let target = match location {
+            Location::Address(address) => address.address() as i64,
+            Location::Offset(offset) => index as i64 + offset.offset() as i64,
+            Location::Label(label) => {
+                let label = label.to_string();
+                match resolve_label(symbols, &label) {
+                    Some(target) => target as i64,
+                    None => {
+                        errors.push(LinkError {
+                            index,
+                            kind: LinkErrorKind::UndefinedLabel(label)
+                        });
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if target < 0 || target > address::MAX_VALUE as i64 {
+            errors.push(LinkError {
+                index,
+                kind: LinkErrorKind::TargetOutOfRange(target)
+            });
+            continue;
+        }
+
+        let target = target as u32;
+        if target as usize >= length {
+            errors.push(LinkError {
+                index,
+                kind: LinkErrorKind::TargetPastProgram(target)
+            });
+            continue;
+        }
+
+        *location = Location::Address(Address::new(target));
+    }
+    
+    if errors.is_empty() {
+        Ok(linked)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batpu_assembly::components::offset::Offset;
+
+    fn label(name: &str) -> Location {
+        Location::Label(name.into())
+    }
+
+    fn offset(offset: i32) -> Location {
+        Location::Offset(Offset::new(offset))
+    }
+
+    fn target(instruction: &Instruction) -> u32 {
+        match location(instruction) {
+            Some(Location::Address(address)) => address.address(),
+            other => panic!("Expected a resolved address, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn resolves_labels_from_the_symbol_table() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(String::from("loop"), 1);
+
+        let linked = link(&[Instruction::NoOperation, Instruction::NoOperation, Instruction::Jump(label("loop"))], &symbols).unwrap();
+        assert_eq!(target(&linked[2]), 1);
+    }
+
+    #[test]
+    fn resolves_offsets_relative_to_the_instruction() {
+        let instructions = [
+            Instruction::NoOperation,
+            Instruction::Call(offset(2)),
+            Instruction::NoOperation,
+            Instruction::Jump(offset(-3))
+        ];
+
+        let linked = link(&instructions, &SymbolTable::new()).unwrap();
+        assert_eq!(target(&linked[1]), 3);
+        assert_eq!(target(&linked[3]), 0);
+    }
+
+    #[test]
+    fn reports_targets_before_the_program() {
+        let errors = link(&[Instruction::Jump(offset(-2))], &SymbolTable::new()).unwrap_err();
+
+        assert_eq!(errors, vec![LinkError {
+            index: 0,
+            kind: LinkErrorKind::TargetOutOfRange(-2)
+        }]);
+    }
+
+    #[test]
+    fn leaves_other_instructions_untouched() {
+        let instructions = [Instruction::Halt, Instruction::Return];
+        assert_eq!(link(&instructions, &SymbolTable::new()).unwrap(), instructions.to_vec());
+    }
+
+    #[test]
+    fn reports_undefined_labels() {
+        let errors = link(&[Instruction::Jump(label("missing"))], &SymbolTable::new()).unwrap_err();
+
+        assert_eq!(errors, vec![LinkError {
+            index: 0,
+            kind: LinkErrorKind::UndefinedLabel(String::from("missing"))
+        }]);
+    }
+
+    #[test]
+    fn reports_targets_past_the_program() {
+        let errors = link(&[Instruction::Jump(offset(5))], &SymbolTable::new()).unwrap_err();
+
+        assert_eq!(errors, vec![LinkError {
+            index: 0,
+            kind: LinkErrorKind::TargetPastProgram(5)
+        }]);
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let instructions = [Instruction::Jump(label("missing")), Instruction::Jump(offset(-2))];
+        let errors = link(&instructions, &SymbolTable::new()).unwrap_err();
+
+        assert_eq!(errors.iter().map(|error| error.index).collect::<Vec<usize>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn reports_the_first_instruction_past_the_address_space() {
+        let capacity = address::MAX_POSSIBLE_COUNT as usize;
+        let instructions = vec![Instruction::NoOperation; capacity + 3];
+        let errors = link(&instructions, &SymbolTable::new()).unwrap_err();
+
+        assert_eq!(errors, vec![LinkError {
+            index: capacity,
+            kind: LinkErrorKind::ProgramTooLong(capacity + 3)
+        }]);
+    }
+}
\ No newline at end of file
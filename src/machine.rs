@@ -1,19 +1,35 @@
-use crate::components::character_display::CharacterDisplay;
+pub mod run;
+pub mod rewind;
+pub mod state;
+pub mod clock;
+pub mod config;
+pub mod loader;
+pub mod reload;
+mod decode;
+
+use crate::bus::{Bus, Device, DeviceId};
+use crate::components::character_display::{CharacterDisplay, DEFAULT_CHARACTERS};
 use crate::components::controller::Controller;
 use crate::components::number_display::NumberDisplay;
+use crate::components::random::RandomSource;
+use crate::components::screen;
 use crate::components::screen::Screen;
 use crate::components::stack::Stack;
+use crate::debugger::expression::Context;
+use crate::debugger::{Debugger, WatchHit, Watchpoint};
+use crate::error::{MachineError, MachineErrorKind};
+use crate::linker::{link, location, LinkError, SymbolTable};
+use crate::machine::config::{MachineConfig, ProgramEndPolicy};
+use crate::machine::decode::{decode, Flag, Op, Target};
+use crate::machine::rewind::History;
+use crate::machine_code::{export, EncodeError, ExportFormat};
+use crate::source_map::SourceMap;
+use crate::trace::{PortAccess, TraceFormat, TraceStep, Tracer};
 use batpu_assembly::components::address;
-use batpu_assembly::components::condition::Condition;
 use batpu_assembly::components::immediate;
 use batpu_assembly::components::location::Location;
-use batpu_assembly::components::register::Register;
 use batpu_assembly::instruction::Instruction;
 use batpu_assembly::InstructionVec;
-use rand::rngs::ThreadRng;
-use rand::{rng, Rng};
-
-pub const CHARACTERS: &[char] = &[' ', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '.', '!', '?'];
 
 pub const PORTS: usize = 16;
 pub const REGISTER_COUNT: usize = 16;
@@ -21,13 +37,38 @@ pub const MEMORY_SIZE: usize = 256;
 pub const USABLE_MEMORY_SIZE: usize = MEMORY_SIZE - PORTS;
 pub const PORTS_ADDRESS: usize = MEMORY_SIZE - PORTS;
 
+#[deprecated(note = "use character_display::DEFAULT_CHARACTERS, or MachineConfig::characters for a custom set")]
+pub const CHARACTERS: &[char] = DEFAULT_CHARACTERS;
+
 pub type Word = u8;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    FramePushed,
+    Halted,
+    Breakpoint(u32),
+    Watchpoint {
+        hits: Vec<WatchHit>,
+        frame_pushed: bool
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HaltBehaviour {
+    ResetProgramCounter,
+    KeepProgramCounter
+}
+
 pub struct Machine {
-    rng: ThreadRng,
+    random: Box<dyn RandomSource>,
 
     program_counter: u32,
     halt: bool,
+    halt_behaviour: HaltBehaviour,
+    program_end: ProgramEndPolicy,
+    cycles: u64,
 
     registers: [Word; REGISTER_COUNT],
     memory: [Word; USABLE_MEMORY_SIZE],
@@ -45,37 +86,25 @@ pub struct Machine {
     character_display: CharacterDisplay,
     number_display: NumberDisplay,
     controller: Controller,
-
-    instructions: InstructionVec
+    bus: Bus,
+    
+    frame_pushed: bool,
+    debugger: Debugger,
+    tracer: Option<Tracer>,
+    history: Option<History>,
+
+    instructions: InstructionVec,
+    source_map: Option<SourceMap>,
+    ops: Vec<Op>
 }
 
 impl Machine {
     pub fn new() -> Self {
-        Self {
-            rng: rng(),
-
-            program_counter: 0,
-            halt: false,
-
-            registers: [0; REGISTER_COUNT],
-            memory: [0; USABLE_MEMORY_SIZE],
-            stack: Stack::new(16),
-            
-            registers_updated: true,
-            memory_updated: true,
-            
-            zero_flag: false,
-            carry_flag: false,
-            
-            flags_updated: true,
-
-            screen: Screen::new(32, 32),
-            character_display: CharacterDisplay::new(10),
-            number_display: NumberDisplay::new(),
-            controller: Controller::new(),
-
-            instructions: Vec::new()
-        }
+        Self::from_config(MachineConfig::new())
+    }
+    
+    pub fn with_random(random: Box<dyn RandomSource>) -> Self {
+        Self::from_config(MachineConfig::new().random(random))
     }
     
     pub fn reset(&mut self) {
@@ -97,181 +126,345 @@ impl Machine {
         self.controller.clear();
         
         self.program_counter = 0;
+        self.halt = false;
+        self.cycles = 0;
+        
+        if let Some(history) = &mut self.history {
+            history.restart(0);
+        }
     }
     
     pub fn set_instructions(&mut self, instructions: InstructionVec) {
+        self.ops = instructions.iter().map(decode).collect();
         self.instructions = instructions;
+        self.source_map = None;
+    }
+    
+    pub fn load_instructions(&mut self, instructions: InstructionVec, symbols: &SymbolTable) -> Result<(), Vec<LinkError>> {
+        let instructions = link(&instructions, symbols)?;
+        self.set_instructions(instructions);
+        
+        Ok(())
+    }
+    
+    pub fn load_program(&mut self, instructions: InstructionVec, source_map: SourceMap) -> Result<(), Vec<LinkError>> {
+        self.load_instructions(instructions, &source_map.symbols())?;
+        self.source_map = Some(source_map);
+        
+        Ok(())
+    }
+    
+    pub fn instructions(&self) -> &InstructionVec {
+        &self.instructions
+    }
+    
+    pub fn export_program(&self, format: ExportFormat) -> Result<Vec<u8>, Vec<EncodeError>> {
+        export(&self.instructions, format)
+    }
+    
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+    
+    pub fn set_source_map(&mut self, source_map: Option<SourceMap>) {
+        self.source_map = source_map;
+    }
+    
+    pub fn describe(&self, address: u32) -> String {
+        match &self.source_map {
+            Some(source_map) => source_map.describe(address),
+            None => address.to_string()
+        }
     }
 
-    pub fn tick(&mut self) {
-        if self.program_counter >= self.instructions.len() as u32 {
-            self.program_counter = (self.program_counter + 1).rem_euclid(address::MAX_POSSIBLE_COUNT);
-            return;
+    fn locate(&self, mut error: MachineError) -> MachineError {
+        if let Some(source_map) = &self.source_map {
+            error.location = Some(source_map.describe(error.address()));
+        }
+        
+        error
+    }
+
+    pub fn tick(&mut self) -> Result<StepOutcome, MachineError> {
+        self.tick_with(|machine, index| machine.ops[index])
+    }
+    
+    /// Clones and decodes the instruction on every tick, bypassing the decoded cache. Only kept for benchmarks.
+    #[doc(hidden)]
+    pub fn tick_uncached(&mut self) -> Result<StepOutcome, MachineError> {
+        self.tick_with(|machine, index| decode(&machine.instructions[index].clone()))
+    }
+    
+    fn tick_with(&mut self, fetch: impl Fn(&Self, usize) -> Op) -> Result<StepOutcome, MachineError> {
+        if self.halt {
+            return Ok(StepOutcome::Halted);
         }
 
-        let instruction = self.instructions[self.program_counter as usize].clone();
-        self.run_instruction(&instruction);
+        if self.program_counter >= self.ops.len() as u32 {
+            if self.program_end == ProgramEndPolicy::Halt {
+                return Ok(self.stop());
+            }
+            
+            return Err(self.locate(MachineErrorKind::ProgramCounterOutOfRange {
+                program_counter: self.program_counter,
+                length: self.ops.len()
+            }.into()));
+        }
+
+        let address = self.program_counter;
+        if self.debugger.armed(address) && self.debugger.condition_met(address, &Context::new(self)) && self.debugger.hit_breakpoint(address) {
+            return Ok(StepOutcome::Breakpoint(address));
+        }
+
+        self.frame_pushed = false;
+        
+        if let Some(tracer) = &mut self.tracer {
+            tracer.begin(&self.registers, self.zero_flag, self.carry_flag);
+        }
+
+        let program_counter = self.program_counter;
+        let op = fetch(self, program_counter as usize);
+        
+        let undo = self.history.as_ref().map(|_| self.undo_record(op));
+        
+        let result = self.run_op(op);
+        let hits = self.debugger.take_hits();
+        
+        if let Some(tracer) = &mut self.tracer {
+            let source = match (&self.source_map, tracer.format()) {
+                (Some(source_map), TraceFormat::Text) => Some(source_map.describe(program_counter)),
+                _ => None
+            };
+            
+            tracer.end(TraceStep {
+                cycle: self.cycles,
+                program_counter,
+                instruction: &self.instructions[program_counter as usize],
+                source,
+                fault: result.as_ref().err().cloned(),
+                registers: &self.registers,
+                zero_flag: self.zero_flag,
+                carry_flag: self.carry_flag
+            });
+        }
+        
+        let outcome = result.map_err(|error| self.locate(error))?;
+        
+        self.cycles += 1;
+        
+        if let (Some(history), Some(undo)) = (&mut self.history, undo) {
+            history.push(undo);
+        }
+        
+        if let Some(hits) = hits {
+            return Ok(StepOutcome::Watchpoint {
+                hits,
+                frame_pushed: self.frame_pushed
+            });
+        }
+        
+        if outcome == StepOutcome::Executed && self.frame_pushed {
+            return Ok(StepOutcome::FramePushed);
+        }
+        
+        Ok(outcome)
     }
     
-    fn run_instruction(&mut self, instruction: &Instruction) {
-        match instruction {
-            Instruction::NoOperation => {},
-            Instruction::Halt => {
-                self.halt = true;
-                self.program_counter = 0;
-                return;
-            },
-            Instruction::Addition(a, b, c) => {
-                let (result, borrow) = self.reg(&a).overflowing_add(self.reg(&b));
+    fn run_op(&mut self, op: Op) -> Result<StepOutcome, MachineError> {
+        match op {
+            Op::NoOperation => {},
+            Op::Halt => return Ok(self.stop()),
+            Op::Addition(a, b, c) => {
+                let (result, borrow) = self.reg(a).overflowing_add(self.reg(b));
 
                 self.set_carry_flag(borrow);
                 self.set_zero_flag(result == 0);
 
                 self.set_reg(
-                    &c,
+                    c,
                     result
                 );
             },
-            Instruction::Subtraction(a, b, c) => {
-                let (result, borrow) = self.reg(&a).overflowing_sub(self.reg(&b));
+            Op::Subtraction(a, b, c) => {
+                let (result, borrow) = self.reg(a).overflowing_sub(self.reg(b));
 
                 self.set_carry_flag(!borrow);
                 self.set_zero_flag(result == 0);
 
                 self.set_reg(
-                    &c,
+                    c,
                     result
                 );
             },
-            Instruction::BitwiseNOR(a, b, c) => {
-                let result = !(self.reg(&a) | self.reg(&b));
+            Op::BitwiseNOR(a, b, c) => {
+                let result = !(self.reg(a) | self.reg(b));
 
                 self.set_zero_flag(result == 0);
                 self.set_carry_flag(false);
 
                 self.set_reg(
-                    &c,
+                    c,
                     result
                 );
             },
-            Instruction::BitwiseAND(a, b, c) => {
-                let result = self.reg(&a) & self.reg(&b);
+            Op::BitwiseAND(a, b, c) => {
+                let result = self.reg(a) & self.reg(b);
 
                 self.set_zero_flag(result == 0);
                 self.set_carry_flag(false);
 
                 self.set_reg(
-                    &c,
+                    c,
                     result
                 );
             },
-            Instruction::BitwiseXOR(a, b, c) => {
-                let result = self.reg(&a) ^ self.reg(&b);
+            Op::BitwiseXOR(a, b, c) => {
+                let result = self.reg(a) ^ self.reg(b);
 
                 self.set_zero_flag(result == 0);
                 self.set_carry_flag(false);
 
                 self.set_reg(
-                    &c,
+                    c,
                     result
                 );
             },
-            Instruction::RightShift(a, c) => {
+            Op::RightShift(a, c) => {
                 self.set_reg(
-                    &c,
-                    self.reg(&a) >> 1
+                    c,
+                    self.reg(a) >> 1
                 );
             },
-            Instruction::LoadImmediate(a, immediate) => {
+            Op::LoadImmediate(a, immediate) => {
                 self.set_reg(
-                    &a,
-                    immediate.immediate() as u8
+                    a,
+                    immediate
                 );
             },
-            Instruction::AddImmediate(a, immediate) => {
-                let (result, borrow) = self.reg(&a).overflowing_add(immediate.immediate() as Word);
+            Op::AddImmediate(a, immediate) => {
+                let (result, borrow) = self.reg(a).overflowing_add(immediate);
 
                 self.set_carry_flag(borrow);
                 self.set_zero_flag(result == 0);
 
                 self.set_reg(
-                    &a,
+                    a,
                     result
                 );
             },
-            Instruction::Jump(location) => {
-                match location {
-                    Location::Address(address) => {
-                        self.program_counter = address.address();
-                        return;
-                    },
-                    Location::Offset(_) => {
-                        panic!("Attempted to jump to an offset");
-                    },
-                    Location::Label(_) => {
-                        panic!("Attempted to jump to a label");
-                    }
-                }
+            Op::Jump(target) => {
+                self.program_counter = self.target(target)?;
+                return Ok(StepOutcome::Executed);
             },
-            Instruction::Branch(condition, location) => {
-                let condition_met = match condition {
-                    Condition::Zero     => self.zero_flag,
-                    Condition::NotZero  => !self.zero_flag,
-                    Condition::Carry    => self.carry_flag,
-                    Condition::NotCarry => !self.carry_flag
+            Op::Branch(flag, target) => {
+                let condition_met = match flag {
+                    Flag::Zero     => self.zero_flag,
+                    Flag::NotZero  => !self.zero_flag,
+                    Flag::Carry    => self.carry_flag,
+                    Flag::NotCarry => !self.carry_flag
                 };
 
                 if condition_met {
-                    match location {
-                        Location::Address(address) => {
-                            self.program_counter = address.address();
-                            return;
-                        },
-                        Location::Offset(_) => {
-                            panic!("Attempted to jump to an offset");
-                        },
-                        Location::Label(_) => {
-                            panic!("Attempted to jump to a label");
-                        }
-                    }
+                    self.program_counter = self.target(target)?;
+                    return Ok(StepOutcome::Executed);
                 }
             }
-            Instruction::Call(location) => {
-                match location {
-                    Location::Address(address) => {
-                        self.stack.push((self.program_counter + 1).rem_euclid(address::MAX_POSSIBLE_COUNT));
-                        self.program_counter = address.address();
-                        return;
-                    },
-                    Location::Offset(_) => {
-                        panic!("Attempted to jump to an offset");
-                    },
-                    Location::Label(_) => {
-                        panic!("Attempted to jump to a label");
-                    }
-                }
+            Op::Call(target) => {
+                let target = self.target(target)?;
+                
+                self.stack
+                    .push((self.program_counter + 1).rem_euclid(address::MAX_POSSIBLE_COUNT))
+                    .map_err(|fault| MachineErrorKind::StackFault {
+                        address: self.program_counter,
+                        fault
+                    })?;
+                
+                self.program_counter = target;
+                return Ok(StepOutcome::Executed);
             },
-            Instruction::Return => {
-                self.program_counter = self.stack.pop();
-                return;
+            Op::Return => {
+                self.program_counter = self.stack
+                    .pop()
+                    .map_err(|fault| MachineErrorKind::StackFault {
+                        address: self.program_counter,
+                        fault
+                    })?;
+                
+                return Ok(StepOutcome::Executed);
             },
-            Instruction::MemoryLoad(a, b, offset) => {
-                let mem = self.mem(self.reg(&a) as i32 + offset.offset());
+            Op::MemoryLoad(a, b, offset) => {
+                let mem = self.mem(self.reg(a) as i32 + offset)?;
 
                 self.set_reg(
-                    &b,
+                    b,
                     mem
                 );
             },
-            Instruction::MemoryStore(a, b, offset) => {
+            Op::MemoryStore(a, b, offset) => {
                 self.set_mem(
-                    self.reg(&a) as i32 + offset.offset(),
-                    self.reg(&b)
-                );
+                    self.reg(a) as i32 + offset,
+                    self.reg(b)
+                )?;
             }
         }
 
         self.program_counter += 1;
+        Ok(StepOutcome::Executed)
+    }
+    
+    fn stop(&mut self) -> StepOutcome {
+        self.halt = true;
+        
+        if self.halt_behaviour == HaltBehaviour::ResetProgramCounter {
+            self.program_counter = 0;
+        }
+        
+        StepOutcome::Halted
+    }
+    
+    fn target(&self, target: Target) -> Result<u32, MachineError> {
+        match target {
+            Target::Address(address) => Ok(address),
+            Target::Offset(offset) => Err(MachineErrorKind::UnresolvedOffset {
+                address: self.program_counter,
+                offset
+            }.into()),
+            Target::Label => {
+                let label = match self.instructions.get(self.program_counter as usize).and_then(location) {
+                    Some(Location::Label(label)) => label.to_string(),
+                    _ => String::new()
+                };
+                
+                Err(MachineErrorKind::UnresolvedLabel {
+                    address: self.program_counter,
+                    label
+                }.into())
+            }
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+    
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+    
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+    
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn set_random(&mut self, random: Box<dyn RandomSource>) {
+        self.random = random;
     }
 
     pub fn program_counter(&self) -> u32 {
@@ -290,6 +483,39 @@ impl Machine {
         self.halt = halt;
     }
     
+    pub fn resume(&mut self) {
+        if !self.halt {
+            return;
+        }
+        
+        self.halt = false;
+        
+        if self.halt_behaviour == HaltBehaviour::KeepProgramCounter
+            && let Some(Instruction::Halt) = self.instructions.get(self.program_counter as usize) {
+            self.program_counter = (self.program_counter + 1).rem_euclid(address::MAX_POSSIBLE_COUNT);
+        }
+    }
+    
+    pub fn program_end(&self) -> ProgramEndPolicy {
+        self.program_end
+    }
+    
+    pub fn set_program_end(&mut self, program_end: ProgramEndPolicy) {
+        self.program_end = program_end;
+    }
+    
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+    
+    pub fn halt_behaviour(&self) -> HaltBehaviour {
+        self.halt_behaviour
+    }
+    
+    pub fn set_halt_behaviour(&mut self, halt_behaviour: HaltBehaviour) {
+        self.halt_behaviour = halt_behaviour;
+    }
+    
     pub fn zero_flag(&self) -> bool {
         self.zero_flag
     }
@@ -370,6 +596,14 @@ impl Machine {
         &mut self.controller
     }
 
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+    
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
     pub fn registers_updated(&self) -> bool {
         self.registers_updated
     }
@@ -394,77 +628,241 @@ impl Machine {
         self.flags_updated = false;
     }
 
-    fn reg(&self, register: &Register) -> Word {
-        let register = register.register();
-        self.registers[register as usize]
+    fn reg(&self, register: usize) -> Word {
+        self.registers[register]
     }
 
-    fn set_reg(&mut self, register: &Register, value: Word) {
-        let register = register.register();
+    fn set_reg(&mut self, register: usize, value: Word) {
         if register == 0 {
             return;
         }
         
-        self.registers[register as usize] = value;
+        let old = self.registers[register];
+        self.debugger.watch(Watchpoint::Register(register), self.program_counter, Some(old), value);
+        
+        self.registers[register] = value;
         self.registers_updated = true;
     }
 
-    fn mem(&mut self, address: i32) -> Word {
+    fn mem(&mut self, address: i32) -> Result<Word, MachineError> {
         let address = address.rem_euclid(immediate::MAX_POSSIBLE_COUNT as i32) as usize;
         
         if address >= PORTS_ADDRESS {
-            return match address - PORTS_ADDRESS {
-                0  => 0,
-                1  => 0,
-                2  => 0,
-                3  => 0,
-                4  => if self.screen.pix() { 1 } else { 0 },
-                5  => 0,
-                6  => 0,
-                7  => 0,
-                8  => 0,
-                9  => 0,
-                10 => 0,
-                11 => 0,
-                12 => 0,
-                13 => 0,
-                14 => self.rng.random(),
-                15 => self.controller.binary(),
-                _ => panic!("I/O address {} not implemented", address)
+            let port = address - PORTS_ADDRESS;
+            let value = match self.bus.port(port) {
+                Some(mapping) => self.device_mut(mapping.device).map_or(0, |device| device.read(mapping.register)),
+                None => 0
+            };
+            
+            self.debugger.watch(Watchpoint::PortRead(port), self.program_counter, None, value);
+            
+            if let Some(tracer) = &mut self.tracer {
+                tracer.port(PortAccess::Read {
+                    port,
+                    value
+                });
             }
+            
+            return Ok(value);
         }
         
-        self.memory[address]
+        let value = self.memory[address];
+        self.debugger.watch(Watchpoint::MemoryRead(address), self.program_counter, None, value);
+        
+        Ok(value)
     }
 
-    fn set_mem(&mut self, address: i32, value: Word) {
+    fn set_mem(&mut self, address: i32, value: Word) -> Result<(), MachineError> {
         let address = address.rem_euclid(immediate::MAX_POSSIBLE_COUNT as i32) as usize;
         
         if address >= PORTS_ADDRESS {
-            match address - PORTS_ADDRESS {
-                0  => self.screen.x = value as isize,
-                1  => self.screen.y = value as isize,
-                2  => self.screen.set_pix(true),
-                3  => self.screen.set_pix(false),
-                4  => (),
-                5  => self.screen.push_buffer(),
-                6  => self.screen.clear_buffer(),
-                7  => { self.character_display.push(CHARACTERS.get(value as usize)); },
-                8  => self.character_display.push_buffer(),
-                9  => self.character_display.clear_buffer(),
-                10 => self.number_display.set_value(value),
-                11 => self.number_display.clear(),
-                12 => self.number_display.signed = true,
-                13 => self.number_display.signed = false,
-                14 => (),
-                15 => (),
-                _ => panic!("I/O address {} not implemented", address)
+            let port = address - PORTS_ADDRESS;
+            if let Some(mapping) = self.bus.port(port) {
+                if let Some(device) = self.device_mut(mapping.device) {
+                    device.write(mapping.register, value);
+                }
+                
+                if mapping.device == DeviceId::Screen && mapping.register == screen::PUSH_BUFFER {
+                    self.frame_pushed = true;
+                }
             }
-
-            return;
+            
+            self.debugger.watch(Watchpoint::PortWrite(port), self.program_counter, None, value);
+            
+            if let Some(tracer) = &mut self.tracer {
+                tracer.port(PortAccess::Write {
+                    port,
+                    value
+                });
+            }
+            
+            return Ok(());
+        }
+        
+        let old = self.memory[address];
+        self.debugger.watch(Watchpoint::MemoryWrite(address), self.program_counter, Some(old), value);
+        
+        if let Some(tracer) = &mut self.tracer {
+            tracer.store(address, old, value);
         }
 
         self.memory[address] = value;
         self.memory_updated = true;
+        
+        Ok(())
+    }
+    
+    fn device_mut(&mut self, device: DeviceId) -> Option<&mut dyn Device> {
+        match device {
+            DeviceId::Screen => Some(&mut self.screen),
+            DeviceId::CharacterDisplay => Some(&mut self.character_display),
+            DeviceId::NumberDisplay => Some(&mut self.number_display),
+            DeviceId::Random => Some(&mut self.random),
+            DeviceId::Controller => Some(&mut self.controller),
+            DeviceId::Custom(index) => self.bus.device_mut(index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::random::SeededRandom;
+    use crate::components::stack::{StackFault, StackPolicy};
+    use crate::test_support::{ldi, load, port, r};
+    use batpu_assembly::components::address::Address;
+    use batpu_assembly::components::immediate::Immediate;
+    use batpu_assembly::components::offset::Offset;
+
+    fn random_words(seed: u64, count: usize) -> Vec<Word> {
+        let mut machine = Machine::with_random(Box::new(SeededRandom::new(seed)));
+        machine.set_instructions(vec![
+            ldi(1, port(14)),
+            load(1, 2),
+            Instruction::Jump(Location::Address(Address::new(1)))
+        ]);
+        machine.tick().unwrap();
+
+        (0..count).map(|_| {
+            machine.tick().unwrap();
+            machine.tick().unwrap();
+            machine.registers()[2]
+        }).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_random_sequence() {
+        assert_eq!(random_words(42, 16), random_words(42, 16));
+        assert_ne!(random_words(42, 16), random_words(43, 16));
+    }
+
+    #[test]
+    fn machine_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Machine>();
+    }
+
+    fn fault(config: MachineConfig, instructions: Vec<Instruction>) -> MachineErrorKind {
+        let mut machine = config.build().unwrap();
+        machine.set_instructions(instructions);
+        machine.set_program_end(ProgramEndPolicy::Fault);
+
+        loop {
+            match machine.tick() {
+                Ok(StepOutcome::Halted) => panic!("machine halted without a fault"),
+                Ok(_) => {},
+                Err(error) => return error.kind
+            }
+        }
+    }
+
+    #[test]
+    fn unresolved_labels_fault() {
+        assert_eq!(fault(MachineConfig::new(), vec![Instruction::NoOperation, Instruction::Jump(Location::Label(".loop".to_string()))]), MachineErrorKind::UnresolvedLabel {
+            address: 1,
+            label: ".loop".to_string()
+        });
+    }
+
+    #[test]
+    fn unresolved_offsets_fault() {
+        assert_eq!(fault(MachineConfig::new(), vec![Instruction::Call(Location::Offset(Offset::new(2)))]), MachineErrorKind::UnresolvedOffset {
+            address: 0,
+            offset: 2
+        });
+    }
+
+    #[test]
+    fn stack_underflow_faults() {
+        assert_eq!(fault(MachineConfig::new().stack_policy(StackPolicy::Trap), vec![Instruction::NoOperation, Instruction::Return]), MachineErrorKind::StackFault {
+            address: 1,
+            fault: StackFault::Underflow
+        });
+    }
+
+    #[test]
+    fn running_off_the_end_faults() {
+        assert_eq!(fault(MachineConfig::new(), vec![Instruction::NoOperation]), MachineErrorKind::ProgramCounterOutOfRange {
+            program_counter: 1,
+            length: 1
+        });
+    }
+
+    fn halted(halt_behaviour: HaltBehaviour) -> Machine {
+        let mut machine = Machine::new();
+        machine.set_halt_behaviour(halt_behaviour);
+        machine.set_instructions(vec![
+            Instruction::AddImmediate(r(1), Immediate::new(1)),
+            Instruction::Halt,
+            Instruction::AddImmediate(r(2), Immediate::new(1))
+        ]);
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.tick(), Ok(StepOutcome::Halted));
+        machine
+    }
+
+    #[test]
+    fn resume_restarts_from_zero() {
+        let mut machine = halted(HaltBehaviour::ResetProgramCounter);
+        assert_eq!(machine.program_counter(), 0);
+
+        machine.resume();
+        assert!(!machine.halt());
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.registers()[1], 2);
+        assert_eq!(machine.registers()[2], 0);
+    }
+
+    #[test]
+    fn resume_continues_past_the_halt() {
+        let mut machine = halted(HaltBehaviour::KeepProgramCounter);
+        assert_eq!(machine.program_counter(), 1);
+
+        machine.resume();
+        assert!(!machine.halt());
+        assert_eq!(machine.program_counter(), 2);
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.registers()[1], 1);
+        assert_eq!(machine.registers()[2], 1);
+    }
+
+    #[test]
+    fn halted_machines_stay_inert() {
+        for halt_behaviour in [HaltBehaviour::ResetProgramCounter, HaltBehaviour::KeepProgramCounter] {
+            let mut machine = halted(halt_behaviour);
+            let program_counter = machine.program_counter();
+            let cycles = machine.cycles();
+
+            for _ in 0..4 {
+                assert_eq!(machine.tick(), Ok(StepOutcome::Halted));
+            }
+
+            assert!(machine.halt());
+            assert_eq!(machine.program_counter(), program_counter);
+            assert_eq!(machine.cycles(), cycles);
+            assert_eq!(machine.registers()[1], 1);
+            assert_eq!(machine.registers()[2], 0);
+        }
     }
 }
\ No newline at end of file
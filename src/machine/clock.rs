@@ -0,0 +1,217 @@
+use crate::machine::run::{RunResult, StopReason};
+use crate::machine::Machine;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_FREQUENCY: f64 = 100_000.0;
+pub const DEFAULT_TURBO_SLICE: Duration = Duration::from_millis(12);
+pub const DEFAULT_MAX_LAG: Duration = Duration::from_millis(250);
+
+const TURBO_BATCH: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockMode {
+    Normal,
+    Turbo,
+    Paused,
+    SlowMotion(f64)
+}
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    frequency: f64,
+    mode: ClockMode,
+
+    turbo_slice: Duration,
+    max_lag: Duration,
+
+    last: Option<Instant>,
+    pending: f64
+}
+
+impl Clock {
+    pub fn new(frequency: f64) -> Self {
+        Self {
+            frequency: frequency.max(0.0),
+            mode: ClockMode::Normal,
+
+            turbo_slice: DEFAULT_TURBO_SLICE,
+            max_lag: DEFAULT_MAX_LAG,
+
+            last: None,
+            pending: 0.0
+        }
+    }
+
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.frequency = frequency.max(0.0);
+    }
+
+    pub fn effective_frequency(&self) -> Option<f64> {
+        match self.mode {
+            ClockMode::Normal => Some(self.frequency),
+            ClockMode::Turbo => None,
+            ClockMode::Paused => Some(0.0),
+            ClockMode::SlowMotion(factor) => Some(self.frequency * factor.clamp(0.0, 1.0))
+        }
+    }
+
+    pub fn mode(&self) -> ClockMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ClockMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.sync();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == ClockMode::Paused
+    }
+
+    pub fn turbo_slice(&self) -> Duration {
+        self.turbo_slice
+    }
+
+    pub fn set_turbo_slice(&mut self, turbo_slice: Duration) {
+        self.turbo_slice = turbo_slice;
+    }
+
+    pub fn max_lag(&self) -> Duration {
+        self.max_lag
+    }
+
+    pub fn set_max_lag(&mut self, max_lag: Duration) {
+        self.max_lag = max_lag;
+    }
+
+    pub fn sync(&mut self) {
+        self.last = None;
+        self.pending = 0.0;
+    }
+
+    pub fn due(&mut self, now: Instant) -> u64 {
+        let frequency = match self.effective_frequency() {
+            Some(frequency) => frequency,
+            None => return 0
+        };
+
+        let elapsed = match self.last {
+            Some(last) => now.saturating_duration_since(last).min(self.max_lag),
+            None => Duration::ZERO
+        };
+
+        self.last = Some(now);
+        self.pending += elapsed.as_secs_f64() * frequency;
+
+        let cycles = self.pending.floor();
+        self.pending -= cycles;
+
+        cycles as u64
+    }
+
+    pub fn run(&mut self, machine: &mut Machine) -> RunResult {
+        if self.mode == ClockMode::Turbo {
+            return self.run_turbo(machine);
+        }
+
+        let cycles = self.due(Instant::now());
+        let result = machine.run_until_halt(cycles);
+
+        if result.reason != StopReason::CyclesExhausted {
+            self.pending = 0.0;
+        }
+
+        result
+    }
+
+    fn run_turbo(&mut self, machine: &mut Machine) -> RunResult {
+        let start = Instant::now();
+        let mut cycles = 0;
+
+        loop {
+            let result = machine.run_until_halt(TURBO_BATCH);
+            cycles += result.cycles;
+
+            if result.reason != StopReason::CyclesExhausted || start.elapsed() >= self.turbo_slice {
+                self.last = Some(Instant::now());
+                self.pending = 0.0;
+
+                return RunResult {
+                    reason: result.reason,
+                    cycles
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    #[test]
+    fn budgets_cycles_for_the_elapsed_time() {
+        let mut clock = Clock::new(1000.0);
+        let start = Instant::now();
+
+        assert_eq!(clock.due(start), 0);
+        assert_eq!(clock.due(start + millis(10)), 10);
+        assert_eq!(clock.due(start + millis(10)), 0);
+        assert_eq!(clock.due(start + millis(35)), 25);
+    }
+
+    #[test]
+    fn carries_fractional_cycles_over() {
+        let mut clock = Clock::new(100.0);
+        let start = Instant::now();
+
+        clock.due(start);
+        assert_eq!(clock.due(start + millis(15)), 1);
+        assert_eq!(clock.due(start + millis(20)), 1);
+    }
+
+    #[test]
+    fn caps_catch_up_at_the_max_lag() {
+        let mut clock = Clock::new(1000.0);
+        clock.set_max_lag(millis(100));
+        let start = Instant::now();
+
+        clock.due(start);
+        assert_eq!(clock.due(start + Duration::from_secs(5)), 100);
+    }
+
+    #[test]
+    fn pauses_and_resumes_without_a_burst() {
+        let mut clock = Clock::new(1000.0);
+        let start = Instant::now();
+
+        clock.due(start);
+        clock.set_mode(ClockMode::Paused);
+        assert!(clock.is_paused());
+        assert_eq!(clock.due(start + millis(50)), 0);
+
+        clock.set_mode(ClockMode::Normal);
+        assert_eq!(clock.due(start + millis(200)), 0);
+        assert_eq!(clock.due(start + millis(210)), 10);
+    }
+
+    #[test]
+    fn slows_down_in_slow_motion() {
+        let mut clock = Clock::new(1000.0);
+        clock.set_mode(ClockMode::SlowMotion(0.5));
+        let start = Instant::now();
+
+        clock.due(start);
+        assert_eq!(clock.due(start + millis(20)), 10);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::bus::Bus;
+use crate::components::character_display;
+use crate::components::character_display::{CharacterDisplay, DEFAULT_CHARACTERS};
+use crate::components::controller::Controller;
+use crate::components::number_display::NumberDisplay;
+use crate::components::random::{RandomSource, SeededRandom, DEFAULT_SEED};
+use crate::components::screen::Screen;
+use crate::components::stack::{Stack, StackPolicy};
+use crate::components::{screen, stack};
+use crate::debugger::Debugger;
+use crate::machine::{HaltBehaviour, Machine, REGISTER_COUNT, USABLE_MEMORY_SIZE};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+pub const DEFAULT_STACK_SIZE: u32 = 16;
+pub const DEFAULT_SCREEN_WIDTH: usize = 32;
+pub const DEFAULT_SCREEN_HEIGHT: usize = 32;
+pub const DEFAULT_CHARACTER_CAPACITY: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProgramEndPolicy {
+    Fault,
+    Halt
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    StackSize(u32),
+    ScreenSize(usize, usize),
+    CharacterCapacity(usize),
+    CharacterSet(usize)
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::StackSize(size) => write!(f, "Stack size {} is invalid, expected 1-{}", size, stack::MAX_SIZE),
+            ConfigError::ScreenSize(width, height) => {
+                write!(f, "Screen size {}x{} is invalid, expected 1-{} on each side", width, height, screen::MAX_SIDE)
+            },
+            ConfigError::CharacterCapacity(capacity) => {
+                write!(f, "Character display capacity {} is invalid, expected 1-{}", capacity, character_display::MAX_CAPACITY)
+            },
+            ConfigError::CharacterSet(length) => {
+                write!(f, "Character set has {} characters, expected 1-{}", length, character_display::MAX_CHARACTERS)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+pub struct MachineConfig {
+    stack_size: u32,
+    stack_policy: StackPolicy,
+    screen_width: usize,
+    screen_height: usize,
+    character_capacity: usize,
+    characters: Vec<char>,
+    random: Option<Box<dyn RandomSource>>,
+    halt_behaviour: HaltBehaviour,
+    program_end: ProgramEndPolicy
+}
+
+impl MachineConfig {
+    pub fn new() -> Self {
+        Self {
+            stack_size: DEFAULT_STACK_SIZE,
+            stack_policy: StackPolicy::Wrap,
+            screen_width: DEFAULT_SCREEN_WIDTH,
+            screen_height: DEFAULT_SCREEN_HEIGHT,
+            character_capacity: DEFAULT_CHARACTER_CAPACITY,
+            characters: DEFAULT_CHARACTERS.to_vec(),
+            random: None,
+            halt_behaviour: HaltBehaviour::ResetProgramCounter,
+            program_end: ProgramEndPolicy::Fault
+        }
+    }
+
+    pub fn stack_size(mut self, stack_size: u32) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    pub fn stack_policy(mut self, stack_policy: StackPolicy) -> Self {
+        self.stack_policy = stack_policy;
+        self
+    }
+
+    pub fn screen_size(mut self, width: usize, height: usize) -> Self {
+        self.screen_width = width;
+        self.screen_height = height;
+        self
+    }
+
+    pub fn character_capacity(mut self, capacity: usize) -> Self {
+        self.character_capacity = capacity;
+        self
+    }
+
+    pub fn characters(mut self, characters: Vec<char>) -> Self {
+        self.characters = characters;
+        self
+    }
+
+    pub fn random(mut self, random: Box<dyn RandomSource>) -> Self {
+        self.random = Some(random);
+        self
+    }
+
+    pub fn halt_behaviour(mut self, halt_behaviour: HaltBehaviour) -> Self {
+        self.halt_behaviour = halt_behaviour;
+        self
+    }
+
+    pub fn program_end(mut self, program_end: ProgramEndPolicy) -> Self {
+        self.program_end = program_end;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        Stack::check_size(self.stack_size, self.stack_policy)?;
+        Screen::check_size(self.screen_width, self.screen_height)?;
+        CharacterDisplay::check_capacity(self.character_capacity)?;
+        CharacterDisplay::check_characters(&self.characters)
+    }
+
+    pub fn build(self) -> Result<Machine, ConfigError> {
+        Machine::with_config(self)
+    }
+}
+
+impl Machine {
+    pub fn with_config(config: MachineConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self::from_config(config))
+    }
+
+    pub(crate) fn from_config(config: MachineConfig) -> Self {
+        Self {
+            random: config.random.unwrap_or_else(|| Box::new(SeededRandom::new(DEFAULT_SEED))),
+
+            program_counter: 0,
+            halt: false,
+            halt_behaviour: config.halt_behaviour,
+            program_end: config.program_end,
+            cycles: 0,
+
+            registers: [0; REGISTER_COUNT],
+            memory: [0; USABLE_MEMORY_SIZE],
+            stack: Stack::with_policy(config.stack_size, config.stack_policy),
+            
+            registers_updated: true,
+            memory_updated: true,
+            
+            zero_flag: false,
+            carry_flag: false,
+            
+            flags_updated: true,
+
+            screen: Screen::new(config.screen_width, config.screen_height),
+            character_display: CharacterDisplay::with_characters(config.character_capacity, config.characters),
+            number_display: NumberDisplay::new(),
+            controller: Controller::new(),
+            bus: Bus::new(),
+            
+            frame_pushed: false,
+            debugger: Debugger::new(),
+            tracer: None,
+            history: None,
+
+            instructions: Vec::new(),
+            source_map: None,
+            ops: Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_defaults_and_the_limits() {
+        assert_eq!(MachineConfig::new().validate(), Ok(()));
+        assert_eq!(MachineConfig::new()
+            .stack_size(stack::MAX_SIZE)
+            .screen_size(screen::MAX_SIDE, screen::MAX_SIDE)
+            .character_capacity(character_display::MAX_CAPACITY)
+            .characters(vec!['#'; character_display::MAX_CHARACTERS])
+            .validate(), Ok(()));
+        assert_eq!(MachineConfig::new().stack_size(0).stack_policy(StackPolicy::Grow).validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_stack_sizes() {
+        assert_eq!(MachineConfig::new().stack_size(0).validate(), Err(ConfigError::StackSize(0)));
+        assert_eq!(MachineConfig::new().stack_size(0).stack_policy(StackPolicy::Trap).validate(), Err(ConfigError::StackSize(0)));
+        assert_eq!(MachineConfig::new().stack_size(stack::MAX_SIZE + 1).stack_policy(StackPolicy::Grow).validate(), Err(ConfigError::StackSize(stack::MAX_SIZE + 1)));
+    }
+
+    #[test]
+    fn rejects_bad_screen_sizes() {
+        assert_eq!(MachineConfig::new().screen_size(0, 32).validate(), Err(ConfigError::ScreenSize(0, 32)));
+        assert_eq!(MachineConfig::new().screen_size(32, 0).validate(), Err(ConfigError::ScreenSize(32, 0)));
+        assert_eq!(MachineConfig::new().screen_size(screen::MAX_SIDE + 1, 32).validate(), Err(ConfigError::ScreenSize(screen::MAX_SIDE + 1, 32)));
+        assert_eq!(MachineConfig::new().screen_size(32, screen::MAX_SIDE + 1).validate(), Err(ConfigError::ScreenSize(32, screen::MAX_SIDE + 1)));
+    }
+
+    #[test]
+    fn rejects_bad_character_displays() {
+        assert_eq!(MachineConfig::new().character_capacity(0).validate(), Err(ConfigError::CharacterCapacity(0)));
+        assert_eq!(MachineConfig::new().character_capacity(character_display::MAX_CAPACITY + 1).validate(), Err(ConfigError::CharacterCapacity(character_display::MAX_CAPACITY + 1)));
+        assert_eq!(MachineConfig::new().characters(Vec::new()).validate(), Err(ConfigError::CharacterSet(0)));
+        assert_eq!(MachineConfig::new().characters(vec!['#'; character_display::MAX_CHARACTERS + 1]).validate(), Err(ConfigError::CharacterSet(character_display::MAX_CHARACTERS + 1)));
+    }
+
+    #[test]
+    fn build_reports_validation_errors() {
+        assert_eq!(MachineConfig::new().screen_size(0, 0).build().err(), Some(ConfigError::ScreenSize(0, 0)));
+    }
+}
\ No newline at end of file
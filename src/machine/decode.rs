@@ -0,0 +1,122 @@
+use crate::machine::Word;
+use batpu_assembly::components::condition::Condition;
+use batpu_assembly::components::location::Location;
+use batpu_assembly::components::register::Register;
+use batpu_assembly::instruction::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flag {
+    Zero,
+    NotZero,
+    Carry,
+    NotCarry
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    Address(u32),
+    Offset(i32),
+    Label
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    NoOperation,
+    Halt,
+    Addition(usize, usize, usize),
+    Subtraction(usize, usize, usize),
+    BitwiseNOR(usize, usize, usize),
+    BitwiseAND(usize, usize, usize),
+    BitwiseXOR(usize, usize, usize),
+    RightShift(usize, usize),
+    LoadImmediate(usize, Word),
+    AddImmediate(usize, Word),
+    Jump(Target),
+    Branch(Flag, Target),
+    Call(Target),
+    Return,
+    MemoryLoad(usize, usize, i32),
+    MemoryStore(usize, usize, i32)
+}
+
+pub(crate) fn decode(instruction: &Instruction) -> Op {
+    match instruction {
+        Instruction::NoOperation => Op::NoOperation,
+        Instruction::Halt => Op::Halt,
+        Instruction::Addition(a, b, c) => Op::Addition(register(a), register(b), register(c)),
+        Instruction::Subtraction(a, b, c) => Op::Subtraction(register(a), register(b), register(c)),
+        Instruction::BitwiseNOR(a, b, c) => Op::BitwiseNOR(register(a), register(b), register(c)),
+        Instruction::BitwiseAND(a, b, c) => Op::BitwiseAND(register(a), register(b), register(c)),
+        Instruction::BitwiseXOR(a, b, c) => Op::BitwiseXOR(register(a), register(b), register(c)),
+        Instruction::RightShift(a, c) => Op::RightShift(register(a), register(c)),
+        Instruction::LoadImmediate(a, immediate) => Op::LoadImmediate(register(a), immediate.immediate() as Word),
+        Instruction::AddImmediate(a, immediate) => Op::AddImmediate(register(a), immediate.immediate() as Word),
+        Instruction::Jump(location) => Op::Jump(target(location)),
+        Instruction::Branch(condition, location) => Op::Branch(flag(condition), target(location)),
+        Instruction::Call(location) => Op::Call(target(location)),
+        Instruction::Return => Op::Return,
+        Instruction::MemoryLoad(a, b, offset) => Op::MemoryLoad(register(a), register(b), offset.offset()),
+        Instruction::MemoryStore(a, b, offset) => Op::MemoryStore(register(a), register(b), offset.offset())
+    }
+}
+
+fn register(register: &Register) -> usize {
+    register.register() as usize
+}
+
+fn target(location: &Location) -> Target {
+    match location {
+        Location::Address(address) => Target::Address(address.address()),
+        Location::Offset(offset) => Target::Offset(offset.offset()),
+        Location::Label(_) => Target::Label
+    }
+}
+
+fn flag(condition: &Condition) -> Flag {
+    match condition {
+        Condition::Zero     => Flag::Zero,
+        Condition::NotZero  => Flag::NotZero,
+        Condition::Carry    => Flag::Carry,
+        Condition::NotCarry => Flag::NotCarry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MachineErrorKind;
+    use crate::linker::{link, SymbolTable};
+    use crate::machine::Machine;
+    use batpu_assembly::components::offset::Offset;
+
+    fn jump(offset: i32) -> Instruction {
+        Instruction::Jump(Location::Offset(Offset::new(offset)))
+    }
+
+    #[test]
+    fn leaves_offsets_unresolved() {
+        assert_eq!(decode(&jump(-3)), Op::Jump(Target::Offset(-3)));
+    }
+
+    #[test]
+    fn unlinked_offsets_fault() {
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![Instruction::NoOperation, jump(-1)]);
+        machine.tick().unwrap();
+
+        assert_eq!(machine.tick().unwrap_err().kind, MachineErrorKind::UnresolvedOffset {
+            address: 1,
+            offset: -1
+        });
+    }
+
+    #[test]
+    fn linked_offsets_run() {
+        let mut machine = Machine::new();
+        machine.set_instructions(link(&[Instruction::NoOperation, jump(-1)], &SymbolTable::new()).unwrap());
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+
+        assert_eq!(machine.program_counter(), 0);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::linker::link;
+use crate::machine::Machine;
+use crate::machine_code::{parse_binary, parse_text, MachineCodeError};
+use crate::source_map::{SourceLocation, SourceMap};
+use batpu_assembly::InstructionVec;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+pub const SOURCE_NAME: &str = "<source>";
+
+const COMMENT_PREFIXES: &[&str] = &["//", ";"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String
+}
+
+impl Diagnostic {
+    pub fn new(file: impl Into<String>, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            line,
+            severity: Severity::Error,
+            message: message.into()
+        }
+    }
+
+    pub fn warning(file: impl Into<String>, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(file, line, message)
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: ", self.file, line)?,
+            None => write!(f, "{}: ", self.file)?
+        }
+
+        if self.severity == Severity::Warning {
+            write!(f, "warning: ")?;
+        }
+
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+struct SourceLine<'a> {
+    number: usize,
+    column: usize,
+    label: Option<&'a str>,
+    define: Option<(&'a str, &'a str)>,
+    instruction: bool
+}
+
+impl Machine {
+    pub fn load_source(&mut self, source: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let (instructions, source_map, warnings) = assemble_source(source, SOURCE_NAME)?;
+
+        self.set_instructions(instructions);
+        self.source_map = Some(source_map);
+
+        Ok(warnings)
+    }
+
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let (instructions, source_map, warnings) = read_file(path.as_ref())?;
+
+        self.set_instructions(instructions);
+        self.source_map = source_map;
+
+        Ok(warnings)
+    }
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<(InstructionVec, Option<SourceMap>, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let file = path.display().to_string();
+    let io_error = |error: std::io::Error| vec![Diagnostic::new(file.clone(), None, error.to_string())];
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("bin") => {
+            let bytes = fs::read(path).map_err(io_error)?;
+            let instructions = parse_binary(&bytes).map_err(|error| vec![machine_code_diagnostic(&file, error)])?;
+
+            Ok((instructions, None, Vec::new()))
+        },
+        Some("mc") => {
+            let source = fs::read_to_string(path).map_err(io_error)?;
+            let instructions = parse_text(&source).map_err(|errors| {
+                errors.into_iter().map(|error| machine_code_diagnostic(&file, error)).collect::<Vec<Diagnostic>>()
+            })?;
+
+            Ok((instructions, None, Vec::new()))
+        },
+        _ => {
+            let source = fs::read_to_string(path).map_err(io_error)?;
+            let (instructions, source_map, warnings) = assemble_source(&source, &file)?;
+
+            Ok((instructions, Some(source_map), warnings))
+        }
+    }
+}
+
+pub(crate) fn assemble_source(source: &str, file: &str) -> Result<(InstructionVec, SourceMap, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let instructions = batpu_assembly::assemble(source).map_err(|error| vec![assembly_diagnostic(file, error.to_string())])?;
+
+    let (source_map, warnings) = build_source_map(&scan(source), &instructions, file);
+    let line_of = |index: usize| source_map.location(index as u32).map(|location| location.line);
+
+    let instructions = link(&instructions, &source_map.symbols()).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| Diagnostic::new(file, line_of(error.index), error.to_string()))
+            .collect::<Vec<Diagnostic>>()
+    })?;
+
+    Ok((instructions, source_map, warnings))
+}
+
+fn machine_code_diagnostic(file: &str, error: MachineCodeError) -> Diagnostic {
+    let line = match error {
+        MachineCodeError::InvalidLength { line, .. } | MachineCodeError::InvalidDigit { line, .. } => Some(line),
+        _ => None
+    };
+
+    Diagnostic::new(file, line, error.to_string())
+}
+
+fn scan(source: &str) -> Vec<SourceLine<'_>> {
+    let mut lines = Vec::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let code = match COMMENT_PREFIXES.iter().filter_map(|prefix| text.find(prefix)).min() {
+            Some(end) => &text[..end],
+            None => text
+        };
+
+        let mut rest = code.trim();
+        if rest.is_empty() {
+            continue;
+        }
+
+        let mut line = SourceLine {
+            number: index + 1,
+            column: code.len() - code.trim_start().len() + 1,
+            label: None,
+            define: None,
+            instruction: false
+        };
+
+        if rest.starts_with('.') {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            line.label = Some(&rest[1..end]);
+            rest = rest[end..].trim();
+        }
+
+        let mut words = rest.split_whitespace();
+        match words.next() {
+            Some(word) if word.eq_ignore_ascii_case("define") => {
+                if let (Some(name), Some(value)) = (words.next(), words.next()) {
+                    line.define = Some((name, value));
+                }
+            },
+            Some(_) => line.instruction = true,
+            None => {}
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn build_source_map(lines: &[SourceLine<'_>], instructions: &InstructionVec, file: &str) -> (SourceMap, Vec<Diagnostic>) {
+    let mut source_map = SourceMap::new();
+    let mut index = 0;
+
+    for line in lines {
+        if let Some(label) = line.label {
+            source_map.add_label(label, index);
+        }
+
+        if let Some((name, value)) = line.define
+            && let Some(value) = parse_value(value) {
+            source_map.add_define(name, value);
+        }
+
+        if line.instruction {
+            index += 1;
+        }
+    }
+
+    if index as usize != instructions.len() {
+        let warning = Diagnostic::warning(file, None, format!(
+            "found {} instruction lines but assembled {} instructions, source locations are unavailable",
+            index,
+            instructions.len()
+        ));
+
+        return (source_map, vec![warning]);
+    }
+
+    for line in lines.iter().filter(|line| line.instruction) {
+        source_map.push_location(SourceLocation::new(file, line.number, line.column));
+    }
+
+    (source_map, Vec::new())
+}
+
+fn assembly_diagnostic(file: &str, message: String) -> Diagnostic {
+    let located = message
+        .strip_prefix("Line ")
+        .and_then(|rest| rest.split_once(':'))
+        .and_then(|(line, rest)| Some((line.parse().ok()?, rest.trim_start())));
+
+    match located {
+        Some((line, rest)) => Diagnostic::new(file, Some(line), rest),
+        None => Diagnostic::new(file, None, message)
+    }
+}
+
+fn parse_value(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+
+    let value = if let Some(hexadecimal) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hexadecimal, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batpu_assembly::instruction::Instruction;
+
+    #[test]
+    fn strips_line_and_semicolon_comments_only() {
+        let lines = scan("LDI r1 1 ; load\n// whole line\nNOP // idle\n  JMP a/b#c\n; NOP\n");
+        let instructions: Vec<_> = lines.iter().filter(|line| line.instruction).map(|line| (line.number, line.column)).collect();
+
+        assert_eq!(instructions, vec![(1, 1), (3, 1), (4, 3)]);
+    }
+
+    #[test]
+    fn maps_instructions_to_their_lines() {
+        let (instructions, source_map, warnings) = assemble_source("define ONE 1\n.start LDI r1 ONE\n\n  JMP .start\n", "main.as").unwrap();
+
+        assert_eq!(instructions.len(), 2);
+        assert!(warnings.is_empty());
+        assert_eq!(source_map.location(1), Some(&SourceLocation::new("main.as", 4, 3)));
+        assert_eq!(source_map.address_of("start"), Some(0));
+        assert_eq!(source_map.defines().get("ONE"), Some(&1));
+    }
+
+    #[test]
+    fn warns_when_locations_cannot_be_mapped() {
+        let lines = scan("NOP\nNOP\n");
+        let (source_map, warnings) = build_source_map(&lines, &vec![Instruction::NoOperation], "main.as");
+
+        assert!(source_map.is_empty());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert!(warnings[0].to_string().starts_with("main.as: warning: found 2 instruction lines but assembled 1"));
+    }
+
+    #[test]
+    fn locates_the_first_failing_line() {
+        for failing in [1, 2, 5, 9] {
+            let source: String = (1..=9)
+                .map(|line| if line == failing { "ADD r1 r2\n" } else { "NOP\n" })
+                .collect();
+
+            let errors = assemble_source(&source, "main.as").unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].line, Some(failing));
+            assert_eq!(errors[0].severity, Severity::Error);
+        }
+    }
+
+    #[test]
+    fn locates_errors_after_labels_and_defines() {
+        let source = "define LIMIT 3\n.loop ADI r1 1\nLDI r2 LIMIT\n// skipped\nBRH nz .loop\nLDI r3 1000\nHLT\n";
+        let errors = assemble_source(source, "main.as").unwrap_err();
+
+        assert_eq!(errors[0].line, Some(6));
+    }
+
+    #[test]
+    fn takes_lines_from_assembler_errors() {
+        assert_eq!(assembly_diagnostic("main.as", "Line 12: bad register".to_string()), Diagnostic::new("main.as", Some(12), "bad register"));
+        assert_eq!(assembly_diagnostic("main.as", "Lines are hard".to_string()), Diagnostic::new("main.as", None, "Lines are hard"));
+
+        let errors = assemble_source(".a NOP\n.a NOP\n", "main.as").unwrap_err();
+        assert_eq!(errors[0].line, None);
+        assert_eq!(errors[0].to_string(), "main.as: Duplicate label .a");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,412 @@
+use crate::linker::{link, LinkError, SymbolTable};
+use crate::machine::loader::{assemble_source, read_file, Diagnostic, SOURCE_NAME};
+use crate::machine::Machine;
+use crate::source_map::SourceMap;
+use batpu_assembly::InstructionVec;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remap {
+    Label {
+        old: u32,
+        new: u32,
+        label: String
+    },
+    Ambiguous {
+        old: u32,
+        new: u32,
+        candidates: Vec<String>
+    },
+    Unmapped {
+        address: u32
+    },
+    OutOfRange {
+        old: u32,
+        new: u32,
+        length: u32
+    }
+}
+
+impl Remap {
+    pub fn old_address(&self) -> u32 {
+        match *self {
+            Remap::Label { old, .. } | Remap::Ambiguous { old, .. } | Remap::OutOfRange { old, .. } => old,
+            Remap::Unmapped { address } => address
+        }
+    }
+
+    pub fn new_address(&self) -> u32 {
+        match *self {
+            Remap::Label { new, .. } | Remap::Ambiguous { new, .. } | Remap::OutOfRange { new, .. } => new,
+            Remap::Unmapped { address } => address
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        match self {
+            Remap::Label { label, .. } => Some(label),
+            _ => None
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Remap::Label { .. })
+    }
+}
+
+impl Display for Remap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Remap::Label { old, new, label } => write!(f, "{} -> {} (label {})", old, new, label),
+            Remap::Ambiguous { old, new, candidates } => {
+                write!(f, "{} -> {} (ambiguous: {})", old, new, candidates.join(", "))
+            },
+            Remap::Unmapped { address } => write!(f, "{} (unmapped, kept as is)", address),
+            Remap::OutOfRange { old, new, length } => {
+                write!(f, "{} -> {} (out of range, the program has {} instructions)", old, new, length)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadReport {
+    pub program_counter: Remap,
+    pub stack: Vec<Remap>,
+    pub breakpoints: Vec<Remap>,
+    pub warnings: Vec<Diagnostic>
+}
+
+impl ReloadReport {
+    pub fn remaps(&self) -> impl Iterator<Item = &Remap> {
+        std::iter::once(&self.program_counter)
+            .chain(&self.stack)
+            .chain(&self.breakpoints)
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.remaps().all(Remap::is_exact)
+    }
+
+    pub fn inexact(&self) -> impl Iterator<Item = &Remap> {
+        self.remaps().filter(|remap| !remap.is_exact())
+    }
+}
+
+struct Remapper<'a> {
+    old: Option<&'a SourceMap>,
+    new: Option<&'a SourceMap>,
+    old_length: u32,
+    new_length: u32
+}
+
+impl Remapper<'_> {
+    fn remap(&self, address: u32) -> Remap {
+        let remap = self.resolve(address);
+        if remap.new_address() < self.new_length {
+            return remap;
+        }
+
+        Remap::OutOfRange {
+            old: address,
+            new: remap.new_address(),
+            length: self.new_length
+        }
+    }
+
+    fn resolve(&self, address: u32) -> Remap {
+        let (old, new) = match (self.old, self.new) {
+            (Some(old), Some(new)) => (old, new),
+            _ => return Remap::Unmapped { address }
+        };
+
+        let (label, old_base) = match old.enclosing_label(address) {
+            Some(enclosing) => enclosing,
+            None => return Remap::Unmapped { address }
+        };
+
+        let mut candidates: Vec<(&str, u32)> = old
+            .labels_at(old_base)
+            .filter_map(|name| Some((name, new.address_of(name)?)))
+            .collect();
+
+        if let Some(index) = candidates.iter().position(|&(name, _)| name == label) {
+            candidates.swap(0, index);
+        }
+
+        let (label, new_base) = match candidates.first() {
+            Some(&candidate) => candidate,
+            None => return Remap::Unmapped { address }
+        };
+
+        let offset = address - old_base;
+        let new_address = new_base + offset;
+
+        if candidates.iter().any(|&(_, base)| base != new_base) {
+            return Remap::Ambiguous {
+                old: address,
+                new: new_address,
+                candidates: candidates.iter().map(|&(name, _)| name.to_string()).collect()
+            };
+        }
+
+        let old_size = region_end(old, old_base, self.old_length) - old_base;
+        let new_size = region_end(new, new_base, self.new_length) - new_base;
+
+        if offset != 0 && old_size != new_size {
+            return Remap::Ambiguous {
+                old: address,
+                new: new_address.min(new_base + new_size.saturating_sub(1)),
+                candidates: vec![label.to_string()]
+            };
+        }
+
+        Remap::Label {
+            old: address,
+            new: new_address,
+            label: label.to_string()
+        }
+    }
+}
+
+fn region_end(source_map: &SourceMap, base: u32, length: u32) -> u32 {
+    source_map
+        .labels()
+        .values()
+        .copied()
+        .filter(|&address| address > base)
+        .min()
+        .unwrap_or(length)
+        .max(base)
+}
+
+fn collide(remap: &Remap, remaps: &[Remap]) -> Remap {
+    let colliding: Vec<&Remap> = remaps
+        .iter()
+        .filter(|other| other.new_address() == remap.new_address())
+        .collect();
+
+    if colliding.len() < 2 {
+        return remap.clone();
+    }
+
+    Remap::Ambiguous {
+        old: remap.old_address(),
+        new: remap.new_address(),
+        candidates: colliding
+            .iter()
+            .map(|other| other.label().map(str::to_string).unwrap_or_else(|| other.old_address().to_string()))
+            .collect()
+    }
+}
+
+impl Machine {
+    pub fn hot_reload(&mut self, instructions: InstructionVec, source_map: Option<SourceMap>) -> Result<ReloadReport, Vec<LinkError>> {
+        let symbols = match &source_map {
+            Some(source_map) => source_map.symbols(),
+            None => SymbolTable::new()
+        };
+
+        let instructions = link(&instructions, &symbols)?;
+        Ok(self.swap_program(instructions, source_map, Vec::new()))
+    }
+
+    pub fn reload_source(&mut self, source: &str) -> Result<ReloadReport, Vec<Diagnostic>> {
+        let (instructions, source_map, warnings) = assemble_source(source, SOURCE_NAME)?;
+        Ok(self.swap_program(instructions, Some(source_map), warnings))
+    }
+
+    pub fn reload_file(&mut self, path: impl AsRef<Path>) -> Result<ReloadReport, Vec<Diagnostic>> {
+        let (instructions, source_map, warnings) = read_file(path.as_ref())?;
+        Ok(self.swap_program(instructions, source_map, warnings))
+    }
+
+    fn swap_program(&mut self, instructions: InstructionVec, source_map: Option<SourceMap>, warnings: Vec<Diagnostic>) -> ReloadReport {
+        let old_map = self.source_map.take();
+        let remapper = Remapper {
+            old: old_map.as_ref(),
+            new: source_map.as_ref(),
+            old_length: self.instructions.len() as u32,
+            new_length: instructions.len() as u32
+        };
+
+        let program_counter = remapper.remap(self.program_counter);
+        self.program_counter = program_counter.new_address();
+
+        let mut stack = Vec::with_capacity(self.stack.stack().len());
+        self.stack.remap(|address| {
+            let remap = remapper.remap(address);
+            let address = remap.new_address();
+
+            stack.push(remap);
+            address
+        });
+
+        let addresses: Vec<u32> = self.debugger.breakpoints().keys().copied().collect();
+        let moved: Vec<_> = addresses
+            .into_iter()
+            .filter_map(|address| Some((address, self.debugger.remove_breakpoint(address)?)))
+            .collect();
+
+        let remaps: Vec<Remap> = moved.iter().map(|&(address, _)| remapper.remap(address)).collect();
+        let breakpoints: Vec<Remap> = remaps.iter().map(|remap| collide(remap, &remaps)).collect();
+
+        for (remap, (_, breakpoint)) in remaps.iter().zip(moved) {
+            if self.debugger.breakpoint(remap.new_address()).is_none() {
+                self.debugger.set_breakpoint(remap.new_address(), breakpoint);
+            }
+        }
+
+        self.debugger.remap_resume_address(|address| {
+            let remap = remapper.remap(address);
+            remap.is_exact().then(|| remap.new_address())
+        });
+
+        self.set_instructions(instructions);
+        self.source_map = source_map;
+
+        if let Some(history) = &mut self.history {
+            history.restart(self.cycles);
+        }
+
+        ReloadReport {
+            program_counter,
+            stack,
+            breakpoints,
+            warnings
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::StepOutcome;
+    use crate::test_support::assembled;
+    use batpu_assembly::instruction::Instruction;
+
+    #[test]
+    fn remaps_exactly_through_labels() {
+        let mut machine = assembled(".start NOP\nNOP\n.loop ADI r1 1\nJMP .loop\n");
+        machine.set_program_counter(3);
+
+        let report = machine.reload_source("NOP\n.start NOP\nNOP\n.loop ADI r1 1\nJMP .loop\n").unwrap();
+
+        assert_eq!(report.program_counter, Remap::Label {
+            old: 3,
+            new: 4,
+            label: "loop".to_string()
+        });
+        assert!(report.is_exact());
+        assert_eq!(machine.program_counter(), 4);
+    }
+
+    #[test]
+    fn diverging_aliases_are_ambiguous() {
+        let mut machine = assembled(".a\n.b NOP\nHLT\n");
+        let report = machine.reload_source(".a NOP\n.b NOP\nHLT\n").unwrap();
+
+        assert_eq!(report.program_counter, Remap::Ambiguous {
+            old: 0,
+            new: 0,
+            candidates: vec!["a".to_string(), "b".to_string()]
+        });
+    }
+
+    #[test]
+    fn resized_regions_are_ambiguous() {
+        let mut machine = assembled(".f NOP\nNOP\nNOP\n.g HLT\n");
+        machine.set_program_counter(2);
+
+        let report = machine.reload_source(".f NOP\nNOP\n.g HLT\n").unwrap();
+
+        assert_eq!(report.program_counter, Remap::Ambiguous {
+            old: 2,
+            new: 1,
+            candidates: vec!["f".to_string()]
+        });
+    }
+
+    #[test]
+    fn addresses_without_a_source_map_are_unmapped() {
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![Instruction::NoOperation, Instruction::NoOperation, Instruction::Halt]);
+        machine.set_program_counter(1);
+
+        let report = machine.hot_reload(vec![Instruction::NoOperation, Instruction::Halt], None).unwrap();
+
+        assert_eq!(report.program_counter, Remap::Unmapped { address: 1 });
+        assert_eq!(machine.program_counter(), 1);
+    }
+
+    #[test]
+    fn addresses_past_the_new_program_are_out_of_range() {
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![Instruction::NoOperation; 4]);
+        machine.set_program_counter(3);
+        machine.debugger_mut().add_breakpoint(2);
+
+        let report = machine.hot_reload(vec![Instruction::Halt; 2], None).unwrap();
+
+        assert_eq!(report.program_counter, Remap::OutOfRange {
+            old: 3,
+            new: 3,
+            length: 2
+        });
+        assert_eq!(report.breakpoints[0], Remap::OutOfRange {
+            old: 2,
+            new: 2,
+            length: 2
+        });
+    }
+
+    #[test]
+    fn colliding_breakpoints_are_ambiguous() {
+        let mut machine = assembled(".a NOP\n.b NOP\nHLT\n");
+        machine.debugger_mut().add_breakpoint(0);
+        machine.debugger_mut().add_breakpoint(1);
+
+        let report = machine.reload_source(".a\n.b NOP\nHLT\n").unwrap();
+        assert_eq!(report.breakpoints.len(), 2);
+
+        for (old, remap) in report.breakpoints.iter().enumerate() {
+            assert_eq!(*remap, Remap::Ambiguous {
+                old: old as u32,
+                new: 0,
+                candidates: vec!["a".to_string(), "b".to_string()]
+            });
+        }
+
+        assert_eq!(machine.debugger().breakpoints().len(), 1);
+    }
+
+    #[test]
+    fn moves_the_resume_address_with_the_program_counter() {
+        let mut machine = assembled(".start NOP\n.loop ADI r1 1\nJMP .loop\n");
+        machine.debugger_mut().add_breakpoint(1);
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.tick(), Ok(StepOutcome::Breakpoint(1)));
+
+        machine.reload_source("NOP\n.start NOP\n.loop ADI r1 1\nJMP .loop\n").unwrap();
+
+        assert_eq!(machine.program_counter(), 2);
+        assert_eq!(machine.tick(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.registers()[1], 1);
+    }
+
+    #[test]
+    fn clears_the_resume_address_when_the_remap_is_inexact() {
+        let mut machine = assembled(".f NOP\nNOP\nNOP\nHLT\n");
+        machine.debugger_mut().add_breakpoint(2);
+        machine.run_for(2);
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Breakpoint(2)));
+
+        let report = machine.reload_source(".f NOP\nNOP\nNOP\nNOP\nHLT\n").unwrap();
+        assert!(!report.program_counter.is_exact());
+
+        assert_eq!(machine.tick(), Ok(StepOutcome::Breakpoint(2)));
+    }
+}
\ No newline at end of file
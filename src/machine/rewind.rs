@@ -0,0 +1,507 @@
+use crate::bus::{DeviceId, PortMapping};
+use crate::components::stack::Stack;
+use crate::components::{character_display, screen};
+use crate::machine::decode::Op;
+use crate::machine::{Machine, Word, PORTS_ADDRESS, REGISTER_COUNT};
+use batpu_assembly::components::immediate;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewindError {
+    Disabled,
+    Exhausted,
+    OutOfRange(u64),
+    Unsupported {
+        cycle: u64,
+        device: DeviceId
+    }
+}
+
+impl Display for RewindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewindError::Disabled => write!(f, "History is disabled"),
+            RewindError::Exhausted => write!(f, "No more history to rewind"),
+            RewindError::OutOfRange(cycle) => write!(f, "Cycle {} is not in the history", cycle),
+            RewindError::Unsupported { cycle, device } => {
+                write!(f, "Cannot rewind past cycle {}, device {:?} does not support rewinding", cycle, device)
+            }
+        }
+    }
+}
+
+impl Error for RewindError {}
+
+enum PortUndo {
+    ScreenPosition {
+        x: isize,
+        y: isize
+    },
+    Pixel {
+        x: isize,
+        y: isize,
+        value: bool
+    },
+    ScreenBuffer(Vec<u8>),
+    ScreenImage(Vec<u8>),
+    CharacterLength(usize),
+    CharacterBuffer(String),
+    CharacterData(String),
+    Number {
+        signed: bool,
+        value: Word
+    },
+    Random(Vec<u8>),
+    Unsupported(DeviceId)
+}
+
+pub(crate) struct UndoRecord {
+    program_counter: u32,
+    halt: bool,
+    
+    registers: [Word; REGISTER_COUNT],
+    zero_flag: bool,
+    carry_flag: bool,
+    
+    memory: Option<(usize, Word)>,
+    stack: Option<Stack>,
+    port: Option<PortUndo>
+}
+
+pub struct History {
+    capacity: usize,
+    records: VecDeque<UndoRecord>,
+    cycle: u64
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+            cycle: 0
+        }
+    }
+    
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+    
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+    
+    pub fn oldest_cycle(&self) -> u64 {
+        self.cycle - self.records.len() as u64
+    }
+    
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+    
+    pub(crate) fn restart(&mut self, cycle: u64) {
+        self.records.clear();
+        self.cycle = cycle;
+    }
+    
+    pub(crate) fn push(&mut self, record: UndoRecord) {
+        self.cycle += 1;
+        
+        if self.capacity == 0 {
+            return;
+        }
+        
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        
+        self.records.push_back(record);
+    }
+    
+    fn check(&self, steps: usize) -> Result<(), RewindError> {
+        if steps > self.records.len() {
+            return Err(RewindError::Exhausted);
+        }
+        
+        for (index, record) in self.records.iter().rev().take(steps).enumerate() {
+            if let Some(PortUndo::Unsupported(device)) = record.port {
+                return Err(RewindError::Unsupported {
+                    cycle: self.cycle - index as u64,
+                    device
+                });
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn pop(&mut self) -> Result<UndoRecord, RewindError> {
+        self.check(1)?;
+        
+        let record = self.records.pop_back().ok_or(RewindError::Exhausted)?;
+        self.cycle -= 1;
+        
+        Ok(record)
+    }
+}
+
+impl Machine {
+    pub fn enable_history(&mut self, capacity: usize) {
+        let mut history = History::new(capacity);
+        history.restart(self.cycles);
+        
+        self.history = Some(history);
+    }
+    
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+    
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+    
+    pub fn step_back(&mut self) -> Result<(), RewindError> {
+        let record = self.history.as_mut().ok_or(RewindError::Disabled)?.pop()?;
+        
+        self.undo(record);
+        self.cycles -= 1;
+        
+        Ok(())
+    }
+    
+    pub fn rewind_to(&mut self, cycle: u64) -> Result<(), RewindError> {
+        let steps = match &self.history {
+            Some(history) if cycle >= history.oldest_cycle() && cycle <= history.cycle() => {
+                let steps = (history.cycle() - cycle) as usize;
+                history.check(steps)?;
+                steps
+            },
+            Some(_) => return Err(RewindError::OutOfRange(cycle)),
+            None => return Err(RewindError::Disabled)
+        };
+        
+        for _ in 0..steps {
+            self.step_back()?;
+        }
+        
+        Ok(())
+    }
+    
+    pub(crate) fn undo_record(&self, op: Op) -> UndoRecord {
+        let mut memory = None;
+        let mut stack = None;
+        let mut port = None;
+        
+        match op {
+            Op::MemoryStore(a, _, offset) => {
+                let address = self.effective_address(a, offset);
+                
+                if address < PORTS_ADDRESS {
+                    memory = Some((address, self.memory[address]));
+                } else {
+                    port = self.port_mapping(address).and_then(|mapping| self.write_undo(mapping));
+                }
+            },
+            Op::MemoryLoad(a, _, offset) => {
+                let address = self.effective_address(a, offset);
+                port = self.port_mapping(address).and_then(|mapping| self.read_undo(mapping));
+            },
+            Op::Call(_) | Op::Return => {
+                stack = Some(self.stack.clone());
+            },
+            _ => {}
+        }
+        
+        UndoRecord {
+            program_counter: self.program_counter,
+            halt: self.halt,
+            
+            registers: self.registers,
+            zero_flag: self.zero_flag,
+            carry_flag: self.carry_flag,
+            
+            memory,
+            stack,
+            port
+        }
+    }
+    
+    fn undo(&mut self, record: UndoRecord) {
+        self.program_counter = record.program_counter;
+        self.halt = record.halt;
+        
+        if self.registers != record.registers {
+            self.registers = record.registers;
+            self.registers_updated = true;
+        }
+        
+        self.set_zero_flag(record.zero_flag);
+        self.set_carry_flag(record.carry_flag);
+        
+        if let Some((address, value)) = record.memory {
+            self.memory[address] = value;
+            self.memory_updated = true;
+        }
+        
+        if let Some(stack) = record.stack {
+            self.stack.restore(stack);
+        }
+        
+        match record.port {
+            Some(PortUndo::ScreenPosition { x, y }) => {
+                self.screen.x = x;
+                self.screen.y = y;
+            },
+            Some(PortUndo::Pixel { x, y, value }) => self.screen.set_buffer_pixel(x, y, value),
+            Some(PortUndo::ScreenBuffer(buffer)) => self.screen.set_buffer(&buffer),
+            Some(PortUndo::ScreenImage(image)) => self.screen.set_image(&image),
+            Some(PortUndo::CharacterLength(length)) => self.character_display.truncate_buffer(length),
+            Some(PortUndo::CharacterBuffer(buffer)) => self.character_display.set_buffer(&buffer),
+            Some(PortUndo::CharacterData(data)) => self.character_display.set_data(&data),
+            Some(PortUndo::Number { signed, value }) => {
+                self.number_display.signed = signed;
+                self.number_display.set_value(value);
+            },
+            Some(PortUndo::Random(state)) => {
+                self.random.set_state(&state);
+            },
+            Some(PortUndo::Unsupported(_)) | None => {}
+        }
+    }
+    
+    fn write_undo(&self, mapping: PortMapping) -> Option<PortUndo> {
+        match (mapping.device, mapping.register) {
+            (DeviceId::Screen, screen::X | screen::Y) => Some(PortUndo::ScreenPosition {
+                x: self.screen.x,
+                y: self.screen.y
+            }),
+            (DeviceId::Screen, screen::DRAW_PIXEL | screen::CLEAR_PIXEL) => Some(PortUndo::Pixel {
+                x: self.screen.x,
+                y: self.screen.y,
+                value: self.screen.pix()
+            }),
+            (DeviceId::Screen, screen::CLEAR_BUFFER) => Some(PortUndo::ScreenBuffer(self.screen.buffer().to_vec())),
+            (DeviceId::Screen, screen::PUSH_BUFFER) => Some(PortUndo::ScreenImage(self.screen.image().to_vec())),
+            (DeviceId::CharacterDisplay, character_display::WRITE_CHARACTER) => {
+                Some(PortUndo::CharacterLength(self.character_display.buffer().len()))
+            },
+            (DeviceId::CharacterDisplay, character_display::CLEAR_BUFFER) => {
+                Some(PortUndo::CharacterBuffer(self.character_display.buffer().to_string()))
+            },
+            (DeviceId::CharacterDisplay, character_display::PUSH_BUFFER) => {
+                Some(PortUndo::CharacterData(self.character_display.data().to_string()))
+            },
+            (DeviceId::NumberDisplay, _) => Some(PortUndo::Number {
+                signed: self.number_display.signed,
+                value: self.number_display.raw_value()
+            }),
+            (DeviceId::Custom(_), _) => Some(PortUndo::Unsupported(mapping.device)),
+            (DeviceId::Screen | DeviceId::CharacterDisplay | DeviceId::Random | DeviceId::Controller, _) => None
+        }
+    }
+    
+    fn read_undo(&self, mapping: PortMapping) -> Option<PortUndo> {
+        match mapping.device {
+            DeviceId::Random => {
+                let state = self.random.state();
+                if state.is_empty() {
+                    Some(PortUndo::Unsupported(DeviceId::Random))
+                } else {
+                    Some(PortUndo::Random(state))
+                }
+            },
+            DeviceId::Custom(_) => Some(PortUndo::Unsupported(mapping.device)),
+            DeviceId::Screen | DeviceId::CharacterDisplay | DeviceId::NumberDisplay | DeviceId::Controller => None
+        }
+    }
+    
+    fn port_mapping(&self, address: usize) -> Option<PortMapping> {
+        if address < PORTS_ADDRESS {
+            return None;
+        }
+        
+        self.bus.port(address - PORTS_ADDRESS)
+    }
+    
+    fn effective_address(&self, register: usize, offset: i32) -> usize {
+        (self.reg(register) as i32 + offset).rem_euclid(immediate::MAX_POSSIBLE_COUNT as i32) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ldi, load, port, store, Latch};
+    use batpu_assembly::components::address::Address;
+    use batpu_assembly::components::location::Location;
+    use batpu_assembly::instruction::Instruction;
+
+    fn machine(instructions: Vec<Instruction>) -> Machine {
+        let mut machine = crate::test_support::machine(instructions);
+        machine.enable_history(16);
+        machine
+    }
+
+    fn step(machine: &mut Machine, cycles: usize) {
+        for _ in 0..cycles {
+            machine.tick().unwrap();
+        }
+    }
+
+    #[test]
+    fn steps_back_registers_and_memory() {
+        let mut machine = machine(vec![ldi(1, 10), ldi(2, 42), store(1, 2), ldi(2, 7)]);
+        step(&mut machine, 4);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.registers()[2], 42);
+        assert_eq!(machine.memory()[10], 42);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.memory()[10], 0);
+        assert_eq!(machine.program_counter(), 2);
+        assert_eq!(machine.cycles(), 2);
+    }
+
+    #[test]
+    fn steps_back_through_calls_and_returns() {
+        let mut machine = machine(vec![
+            Instruction::Call(Location::Address(Address::new(2))),
+            Instruction::Halt,
+            Instruction::Return
+        ]);
+        step(&mut machine, 2);
+        assert_eq!(machine.program_counter(), 1);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.program_counter(), 2);
+        assert_eq!(machine.stack().stack(), &[1]);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.program_counter(), 0);
+        assert_eq!(machine.stack().stack().len(), 0);
+    }
+
+    #[test]
+    fn steps_back_screen_writes() {
+        let mut machine = machine(vec![
+            ldi(1, 3),
+            ldi(2, port(screen::X)),
+            store(2, 1),
+            ldi(2, port(screen::DRAW_PIXEL)),
+            store(2, 0),
+            ldi(2, port(screen::PUSH_BUFFER)),
+            store(2, 0)
+        ]);
+        step(&mut machine, 7);
+        assert!(machine.screen().pixel(3, 0));
+
+        machine.step_back().unwrap();
+        assert!(!machine.screen().pixel(3, 0));
+        assert!(machine.screen().buffer_pixel(3, 0));
+
+        machine.rewind_to(3).unwrap();
+        assert!(!machine.screen().buffer_pixel(3, 0));
+        assert_eq!(machine.screen().x, 3);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.screen().x, 0);
+    }
+
+    #[test]
+    fn steps_back_character_and_number_writes() {
+        let mut machine = machine(vec![
+            ldi(1, 1),
+            ldi(2, port(7 + character_display::WRITE_CHARACTER)),
+            store(2, 1),
+            ldi(2, port(7 + character_display::PUSH_BUFFER)),
+            store(2, 0),
+            ldi(2, port(10)),
+            store(2, 1)
+        ]);
+        step(&mut machine, 7);
+        assert_eq!(machine.character_display().data(), "A");
+        assert_eq!(machine.number_display().value(), 1);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.number_display().value(), 0);
+
+        machine.rewind_to(4).unwrap();
+        assert_eq!(machine.character_display().data(), "");
+        assert_eq!(machine.character_display().buffer(), "A");
+
+        machine.rewind_to(2).unwrap();
+        assert_eq!(machine.character_display().buffer(), "");
+    }
+
+    #[test]
+    fn replays_random_reads() {
+        let mut machine = machine(vec![ldi(1, port(14)), load(1, 2)]);
+        step(&mut machine, 2);
+        let first = machine.registers()[2];
+
+        machine.step_back().unwrap();
+        machine.tick().unwrap();
+        assert_eq!(machine.registers()[2], first);
+    }
+
+    #[test]
+    fn refuses_to_rewind_unsupported_devices() {
+        let mut machine = machine(vec![ldi(1, port(0)), ldi(2, 5), store(1, 2), ldi(3, 1)]);
+        let device = machine.bus_mut().attach(Box::new(Latch(0)));
+        machine.bus_mut().map(0, device, 0);
+        step(&mut machine, 4);
+
+        machine.step_back().unwrap();
+        assert_eq!(machine.step_back(), Err(RewindError::Unsupported { cycle: 3, device }));
+        assert_eq!(machine.cycles(), 3);
+        assert_eq!(machine.rewind_to(0), Err(RewindError::Unsupported { cycle: 3, device }));
+    }
+
+    #[test]
+    fn failed_rewinds_leave_the_machine_untouched() {
+        let mut machine = machine(vec![ldi(1, port(0)), ldi(2, 5), store(1, 2), ldi(3, 1), ldi(4, 2)]);
+        let device = machine.bus_mut().attach(Box::new(Latch(0)));
+        machine.bus_mut().map(0, device, 0);
+        step(&mut machine, 5);
+
+        assert_eq!(machine.rewind_to(1), Err(RewindError::Unsupported { cycle: 3, device }));
+        assert_eq!(machine.cycles(), 5);
+        assert_eq!(machine.program_counter(), 5);
+        assert_eq!(machine.registers()[3], 1);
+        assert_eq!(machine.registers()[4], 2);
+        assert_eq!(machine.history().unwrap().len(), 5);
+
+        machine.rewind_to(3).unwrap();
+        assert_eq!(machine.registers()[3], 0);
+    }
+
+    #[test]
+    fn reports_missing_history() {
+        let mut machine = machine(vec![ldi(1, 1), ldi(1, 2), ldi(1, 3)]);
+        assert_eq!(machine.step_back(), Err(RewindError::Exhausted));
+
+        machine.enable_history(2);
+        step(&mut machine, 3);
+        assert_eq!(machine.history().unwrap().oldest_cycle(), 1);
+        assert_eq!(machine.rewind_to(0), Err(RewindError::OutOfRange(0)));
+
+        machine.rewind_to(1).unwrap();
+        assert_eq!(machine.registers()[1], 1);
+        assert_eq!(machine.step_back(), Err(RewindError::Exhausted));
+
+        machine.disable_history();
+        assert_eq!(machine.step_back(), Err(RewindError::Disabled));
+    }
+}
\ No newline at end of file
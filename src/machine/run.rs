@@ -0,0 +1,257 @@
+use crate::debugger::WatchHit;
+use crate::error::MachineError;
+use crate::machine::{Machine, StepOutcome};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Halted,
+    CyclesExhausted,
+    Breakpoint(u32),
+    Watchpoint {
+        hits: Vec<WatchHit>,
+        frame_pushed: bool
+    },
+    FramePushed,
+    Condition,
+    Fault(MachineError)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult {
+    pub reason: StopReason,
+    pub cycles: u64
+}
+
+impl Machine {
+    pub fn run_for(&mut self, cycles: u64) -> RunResult {
+        self.run(cycles, false, |_| false)
+    }
+
+    pub fn run_until_halt(&mut self, max_cycles: u64) -> RunResult {
+        self.run(max_cycles, false, |_| false)
+    }
+
+    pub fn run_until_frame(&mut self, max_cycles: u64) -> RunResult {
+        self.run(max_cycles, true, |_| false)
+    }
+
+    pub fn run_until<F: FnMut(&Machine) -> bool>(&mut self, max_cycles: u64, predicate: F) -> RunResult {
+        self.run(max_cycles, false, predicate)
+    }
+
+    fn run<F: FnMut(&Machine) -> bool>(&mut self, max_cycles: u64, stop_on_frame: bool, mut predicate: F) -> RunResult {
+        let mut cycles = 0;
+
+        loop {
+            if self.halt {
+                return RunResult {
+                    reason: StopReason::Halted,
+                    cycles
+                };
+            }
+
+            if cycles >= max_cycles {
+                return RunResult {
+                    reason: StopReason::CyclesExhausted,
+                    cycles
+                };
+            }
+
+            let before = self.cycles;
+            let outcome = match self.tick() {
+                Ok(outcome) => outcome,
+                Err(error) => {
+                    return RunResult {
+                        reason: StopReason::Fault(error),
+                        cycles
+                    };
+                }
+            };
+
+            if let StepOutcome::Breakpoint(address) = outcome {
+                return RunResult {
+                    reason: StopReason::Breakpoint(address),
+                    cycles
+                };
+            }
+
+            cycles += self.cycles - before;
+
+            let reason = match outcome {
+                StepOutcome::Watchpoint { hits, frame_pushed } => Some(StopReason::Watchpoint {
+                    hits,
+                    frame_pushed
+                }),
+                StepOutcome::FramePushed if stop_on_frame => Some(StopReason::FramePushed),
+                _ => None
+            };
+
+            if let Some(reason) = reason {
+                return RunResult {
+                    reason,
+                    cycles
+                };
+            }
+
+            if predicate(self) {
+                return RunResult {
+                    reason: StopReason::Condition,
+                    cycles
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::screen;
+    use crate::components::stack::StackPolicy;
+    use crate::machine::config::{MachineConfig, ProgramEndPolicy};
+    use crate::machine::{HaltBehaviour, PORTS_ADDRESS};
+    use crate::source_map::{SourceLocation, SourceMap};
+    use crate::test_support::machine;
+    use batpu_assembly::components::address::Address;
+    use batpu_assembly::components::immediate::Immediate;
+    use batpu_assembly::components::location::Location;
+    use batpu_assembly::components::offset::Offset;
+    use batpu_assembly::components::register::Register;
+    use batpu_assembly::instruction::Instruction;
+
+    fn counter() -> Vec<Instruction> {
+        vec![
+            Instruction::AddImmediate(Register::new(1), Immediate::new(1)),
+            Instruction::Jump(Location::Address(Address::new(0)))
+        ]
+    }
+
+    fn halting() -> Vec<Instruction> {
+        vec![
+            Instruction::NoOperation,
+            Instruction::NoOperation,
+            Instruction::Halt
+        ]
+    }
+
+    #[test]
+    fn run_for_spends_the_whole_budget() {
+        let mut machine = machine(counter());
+        let result = machine.run_for(10);
+
+        assert_eq!(result.reason, StopReason::CyclesExhausted);
+        assert_eq!(result.cycles, 10);
+        assert_eq!(machine.registers()[1], 5);
+    }
+
+    #[test]
+    fn run_for_stops_on_halt() {
+        let mut machine = machine(halting());
+        let result = machine.run_for(10);
+
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.cycles, 3);
+        assert!(machine.halt());
+    }
+
+    #[test]
+    fn run_until_frame_stops_after_a_pushed_frame() {
+        let push_buffer = Immediate::new((PORTS_ADDRESS + screen::PUSH_BUFFER) as i32);
+        let mut machine = machine(vec![
+            Instruction::LoadImmediate(Register::new(1), push_buffer),
+            Instruction::MemoryStore(Register::new(1), Register::new(0), Offset::new(0)),
+            Instruction::NoOperation,
+            Instruction::Halt
+        ]);
+
+        let result = machine.run_until_frame(10);
+        assert_eq!(result.reason, StopReason::FramePushed);
+        assert_eq!(result.cycles, 2);
+
+        let result = machine.run_until_frame(10);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.cycles, 2);
+    }
+
+    #[test]
+    fn run_until_halt_stops_on_halt() {
+        let mut machine = machine(halting());
+        let result = machine.run_until_halt(10);
+
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.cycles, 3);
+
+        let result = machine.run_until_halt(10);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.cycles, 0);
+    }
+
+    #[test]
+    fn run_until_halt_reports_an_exhausted_budget() {
+        let mut machine = machine(counter());
+        let result = machine.run_until_halt(7);
+
+        assert_eq!(result.reason, StopReason::CyclesExhausted);
+        assert_eq!(result.cycles, 7);
+        assert!(!machine.halt());
+    }
+
+    #[test]
+    fn run_until_stops_on_the_predicate() {
+        let mut machine = machine(counter());
+        let result = machine.run_until(100, |machine| machine.registers()[1] == 3);
+
+        assert_eq!(result.reason, StopReason::Condition);
+        assert_eq!(result.cycles, 5);
+    }
+
+    #[test]
+    fn run_until_is_capped() {
+        let mut machine = machine(counter());
+        let result = machine.run_until(50, |_| false);
+
+        assert_eq!(result.reason, StopReason::CyclesExhausted);
+        assert_eq!(result.cycles, 50);
+    }
+
+    #[test]
+    fn faults_stop_the_run() {
+        let mut machine = machine(vec![Instruction::NoOperation]);
+        let result = machine.run_until_halt(10);
+
+        assert!(matches!(result.reason, StopReason::Fault(_)));
+        assert_eq!(result.cycles, 1);
+    }
+
+    #[test]
+    fn faults_carry_the_source_location() {
+        let mut machine = MachineConfig::new().stack_policy(StackPolicy::Trap).build().unwrap();
+        machine.set_instructions(vec![Instruction::NoOperation, Instruction::Return]);
+        
+        let mut source_map = SourceMap::new();
+        source_map.push_location(SourceLocation::new("main.as", 4, 1));
+        source_map.push_location(SourceLocation::new("main.as", 5, 1));
+        machine.set_source_map(Some(source_map));
+
+        let StopReason::Fault(error) = machine.run_until_halt(10).reason else {
+            panic!("expected a fault");
+        };
+        
+        assert_eq!(error.location.as_deref(), Some("main.as:5"));
+        assert!(error.to_string().ends_with(" (main.as:5)"));
+    }
+
+    #[test]
+    fn program_end_halts_like_the_halt_instruction() {
+        for (halt_behaviour, program_counter) in [(HaltBehaviour::ResetProgramCounter, 0), (HaltBehaviour::KeepProgramCounter, 1)] {
+            let mut machine = machine(vec![Instruction::NoOperation]);
+            machine.set_program_end(ProgramEndPolicy::Halt);
+            machine.set_halt_behaviour(halt_behaviour);
+
+            let result = machine.run_until_halt(10);
+            assert_eq!(result.reason, StopReason::Halted);
+            assert_eq!(result.cycles, 1);
+            assert_eq!(machine.program_counter(), program_counter);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,1102 @@
+use crate::bus::{DeviceId, PortMapping};
+use crate::components::character_display::CharacterDisplay;
+use crate::components::controller::Controller;
+use crate::components::number_display::NumberDisplay;
+use crate::components::screen::Screen;
+use crate::components::stack::{Stack, StackPolicy};
+use crate::machine::config::{ConfigError, ProgramEndPolicy};
+use crate::machine::{HaltBehaviour, Machine, Word, PORTS, REGISTER_COUNT, USABLE_MEMORY_SIZE};
+use batpu_assembly::components::address::Address;
+use batpu_assembly::components::condition::Condition;
+use batpu_assembly::components::immediate::Immediate;
+use batpu_assembly::components::location::Location;
+use batpu_assembly::components::offset::Offset;
+use batpu_assembly::components::register::Register;
+use batpu_assembly::components::{address, immediate, offset};
+use batpu_assembly::instruction::Instruction;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{Read, Write};
+
+pub const SAVE_STATE_MAGIC: &[u8; 4] = b"BPSV";
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Corrupt(String)
+}
+
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "I/O error: {}", error),
+            SaveStateError::BadMagic => write!(f, "Not a save state file"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}, expected {}", version, SAVE_STATE_VERSION)
+            },
+            SaveStateError::Corrupt(message) => write!(f, "Corrupt save state: {}", message)
+        }
+    }
+}
+
+impl Error for SaveStateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveStateError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SaveStateError {
+    fn from(error: io::Error) -> Self {
+        SaveStateError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConditionState {
+    Zero,
+    NotZero,
+    Carry,
+    NotCarry
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LocationState {
+    Address(u32),
+    Offset(i32),
+    Label(String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InstructionState {
+    NoOperation,
+    Halt,
+    Addition(u8, u8, u8),
+    Subtraction(u8, u8, u8),
+    BitwiseNOR(u8, u8, u8),
+    BitwiseAND(u8, u8, u8),
+    BitwiseXOR(u8, u8, u8),
+    RightShift(u8, u8),
+    LoadImmediate(u8, i32),
+    AddImmediate(u8, i32),
+    Jump(LocationState),
+    Branch(ConditionState, LocationState),
+    Call(LocationState),
+    Return,
+    MemoryLoad(u8, u8, i32),
+    MemoryStore(u8, u8, i32)
+}
+
+impl From<&Condition> for ConditionState {
+    fn from(condition: &Condition) -> Self {
+        match condition {
+            Condition::Zero     => ConditionState::Zero,
+            Condition::NotZero  => ConditionState::NotZero,
+            Condition::Carry    => ConditionState::Carry,
+            Condition::NotCarry => ConditionState::NotCarry
+        }
+    }
+}
+
+impl ConditionState {
+    pub fn to_condition(&self) -> Condition {
+        match self {
+            ConditionState::Zero     => Condition::Zero,
+            ConditionState::NotZero  => Condition::NotZero,
+            ConditionState::Carry    => Condition::Carry,
+            ConditionState::NotCarry => Condition::NotCarry
+        }
+    }
+}
+
+impl From<&Location> for LocationState {
+    fn from(location: &Location) -> Self {
+        match location {
+            Location::Address(address) => LocationState::Address(address.address()),
+            Location::Offset(offset) => LocationState::Offset(offset.offset()),
+            Location::Label(label) => LocationState::Label(label.to_string())
+        }
+    }
+}
+
+impl LocationState {
+    pub fn validate(&self) -> Result<(), SaveStateError> {
+        match self {
+            LocationState::Address(address) if *address > address::MAX_VALUE => {
+                Err(corrupt(format!("address {} is out of range, expected at most {}", address, address::MAX_VALUE)))
+            },
+            LocationState::Offset(offset) => check_offset(*offset),
+            _ => Ok(())
+        }
+    }
+
+    pub fn to_location(&self) -> Result<Location, SaveStateError> {
+        self.validate()?;
+
+        Ok(match self {
+            LocationState::Address(address) => Location::Address(Address::new(*address as _)),
+            LocationState::Offset(offset) => Location::Offset(Offset::new(*offset as _)),
+            LocationState::Label(label) => Location::Label(label.as_str().into())
+        })
+    }
+}
+
+impl From<&Instruction> for InstructionState {
+    fn from(instruction: &Instruction) -> Self {
+        match instruction {
+            Instruction::NoOperation => InstructionState::NoOperation,
+            Instruction::Halt => InstructionState::Halt,
+            Instruction::Addition(a, b, c) => InstructionState::Addition(register(a), register(b), register(c)),
+            Instruction::Subtraction(a, b, c) => InstructionState::Subtraction(register(a), register(b), register(c)),
+            Instruction::BitwiseNOR(a, b, c) => InstructionState::BitwiseNOR(register(a), register(b), register(c)),
+            Instruction::BitwiseAND(a, b, c) => InstructionState::BitwiseAND(register(a), register(b), register(c)),
+            Instruction::BitwiseXOR(a, b, c) => InstructionState::BitwiseXOR(register(a), register(b), register(c)),
+            Instruction::RightShift(a, c) => InstructionState::RightShift(register(a), register(c)),
+            Instruction::LoadImmediate(a, immediate) => InstructionState::LoadImmediate(register(a), immediate.immediate()),
+            Instruction::AddImmediate(a, immediate) => InstructionState::AddImmediate(register(a), immediate.immediate()),
+            Instruction::Jump(location) => InstructionState::Jump(location.into()),
+            Instruction::Branch(condition, location) => InstructionState::Branch(condition.into(), location.into()),
+            Instruction::Call(location) => InstructionState::Call(location.into()),
+            Instruction::Return => InstructionState::Return,
+            Instruction::MemoryLoad(a, b, offset) => InstructionState::MemoryLoad(register(a), register(b), offset.offset()),
+            Instruction::MemoryStore(a, b, offset) => InstructionState::MemoryStore(register(a), register(b), offset.offset())
+        }
+    }
+}
+
+impl InstructionState {
+    pub fn validate(&self) -> Result<(), SaveStateError> {
+        let registers: &[u8] = match self {
+            InstructionState::Addition(a, b, c)
+            | InstructionState::Subtraction(a, b, c)
+            | InstructionState::BitwiseNOR(a, b, c)
+            | InstructionState::BitwiseAND(a, b, c)
+            | InstructionState::BitwiseXOR(a, b, c) => &[*a, *b, *c],
+            InstructionState::RightShift(a, b) => &[*a, *b],
+            InstructionState::LoadImmediate(a, immediate) | InstructionState::AddImmediate(a, immediate) => {
+                let value = *immediate;
+                if !(immediate::MIN_VALUE..=immediate::MAX_VALUE).contains(&value) {
+                    return Err(corrupt(format!("immediate {} is out of range, expected {}-{}", value, immediate::MIN_VALUE, immediate::MAX_VALUE)));
+                }
+
+                &[*a]
+            },
+            InstructionState::MemoryLoad(a, b, offset) | InstructionState::MemoryStore(a, b, offset) => {
+                check_offset(*offset)?;
+                &[*a, *b]
+            },
+            InstructionState::Jump(location) | InstructionState::Branch(_, location) | InstructionState::Call(location) => {
+                return location.validate();
+            },
+            InstructionState::NoOperation | InstructionState::Halt | InstructionState::Return => &[]
+        };
+
+        match registers.iter().find(|&&register| register as usize >= REGISTER_COUNT) {
+            Some(register) => Err(corrupt(format!("invalid register {}", register))),
+            None => Ok(())
+        }
+    }
+
+    pub fn to_instruction(&self) -> Result<Instruction, SaveStateError> {
+        self.validate()?;
+
+        Ok(match self {
+            InstructionState::NoOperation => Instruction::NoOperation,
+            InstructionState::Halt => Instruction::Halt,
+            InstructionState::Addition(a, b, c) => Instruction::Addition(Register::new(*a as _), Register::new(*b as _), Register::new(*c as _)),
+            InstructionState::Subtraction(a, b, c) => Instruction::Subtraction(Register::new(*a as _), Register::new(*b as _), Register::new(*c as _)),
+            InstructionState::BitwiseNOR(a, b, c) => Instruction::BitwiseNOR(Register::new(*a as _), Register::new(*b as _), Register::new(*c as _)),
+            InstructionState::BitwiseAND(a, b, c) => Instruction::BitwiseAND(Register::new(*a as _), Register::new(*b as _), Register::new(*c as _)),
+            InstructionState::BitwiseXOR(a, b, c) => Instruction::BitwiseXOR(Register::new(*a as _), Register::new(*b as _), Register::new(*c as _)),
+            InstructionState::RightShift(a, c) => Instruction::RightShift(Register::new(*a as _), Register::new(*c as _)),
+            InstructionState::LoadImmediate(a, immediate) => Instruction::LoadImmediate(Register::new(*a as _), Immediate::new(*immediate as _)),
+            InstructionState::AddImmediate(a, immediate) => Instruction::AddImmediate(Register::new(*a as _), Immediate::new(*immediate as _)),
+            InstructionState::Jump(location) => Instruction::Jump(location.to_location()?),
+            InstructionState::Branch(condition, location) => Instruction::Branch(condition.to_condition(), location.to_location()?),
+            InstructionState::Call(location) => Instruction::Call(location.to_location()?),
+            InstructionState::Return => Instruction::Return,
+            InstructionState::MemoryLoad(a, b, offset) => Instruction::MemoryLoad(Register::new(*a as _), Register::new(*b as _), Offset::new(*offset as _)),
+            InstructionState::MemoryStore(a, b, offset) => Instruction::MemoryStore(Register::new(*a as _), Register::new(*b as _), Offset::new(*offset as _))
+        })
+    }
+}
+
+fn register(register: &Register) -> u8 {
+    register.register() as u8
+}
+
+fn check_offset(offset: i32) -> Result<(), SaveStateError> {
+    if !(offset::MIN_VALUE..=offset::MAX_VALUE).contains(&offset) {
+        return Err(corrupt(format!("offset {} is out of range, expected {}-{}", offset, offset::MIN_VALUE, offset::MAX_VALUE)));
+    }
+
+    Ok(())
+}
+
+fn check_config(result: Result<(), ConfigError>) -> Result<(), SaveStateError> {
+    result.map_err(|error| corrupt(error.to_string()))
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineState {
+    pub program_counter: u32,
+    pub halt: bool,
+    pub halt_behaviour: HaltBehaviour,
+    pub program_end: ProgramEndPolicy,
+    pub cycles: u64,
+
+    pub registers: [Word; REGISTER_COUNT],
+    pub memory: Vec<Word>,
+    pub stack: Stack,
+
+    pub zero_flag: bool,
+    pub carry_flag: bool,
+
+    pub screen: Screen,
+    pub character_display: CharacterDisplay,
+    pub number_display: NumberDisplay,
+    pub controller: Controller,
+    pub ports: [Option<PortMapping>; PORTS],
+
+    pub random: Vec<u8>,
+    pub program: Vec<InstructionState>
+}
+
+impl MachineState {
+    pub fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut data = StateWriter::new();
+
+        data.bytes(SAVE_STATE_MAGIC);
+        data.u16(SAVE_STATE_VERSION);
+
+        data.u32(self.program_counter);
+        data.bool(self.halt);
+        data.u8(match self.halt_behaviour {
+            HaltBehaviour::ResetProgramCounter => 0,
+            HaltBehaviour::KeepProgramCounter => 1
+        });
+        data.u8(match self.program_end {
+            ProgramEndPolicy::Fault => 0,
+            ProgramEndPolicy::Halt => 1
+        });
+        data.u64(self.cycles);
+
+        data.bytes(&self.registers);
+        data.u32(self.memory.len() as u32);
+        data.bytes(&self.memory);
+
+        data.u32(self.stack.max_size());
+        data.u8(match self.stack.policy() {
+            StackPolicy::Wrap => 0,
+            StackPolicy::Trap => 1,
+            StackPolicy::Grow => 2
+        });
+        data.u32(self.stack.stack().len() as u32);
+        for &address in self.stack.stack() {
+            data.u32(address);
+        }
+        data.u32(self.stack.high_water_mark() as u32);
+
+        data.bool(self.zero_flag);
+        data.bool(self.carry_flag);
+
+        data.u32(self.screen.width() as u32);
+        data.u32(self.screen.height() as u32);
+        data.i64(self.screen.x as i64);
+        data.i64(self.screen.y as i64);
+        data.u32(self.screen.buffer().len() as u32);
+        data.bytes(self.screen.buffer());
+        data.bytes(self.screen.image());
+
+        data.u32(self.character_display.capacity() as u32);
+        data.string(self.character_display.buffer());
+        data.string(self.character_display.data());
+
+        data.bool(self.number_display.signed);
+        data.u8(self.number_display.raw_value());
+
+        data.u8(self.controller.binary());
+        for mapping in &self.ports {
+            data.port(mapping);
+        }
+
+        data.u32(self.random.len() as u32);
+        data.bytes(&self.random);
+
+        data.u32(self.program.len() as u32);
+        for instruction in &self.program {
+            data.instruction(instruction);
+        }
+
+        writer.write_all(&data.data)
+    }
+
+    pub fn read_from(reader: &mut dyn Read) -> Result<Self, SaveStateError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let mut data = StateReader {
+            data: &bytes,
+            position: 0
+        };
+
+        if data.bytes(SAVE_STATE_MAGIC.len()).ok() != Some(&SAVE_STATE_MAGIC[..]) {
+            return Err(SaveStateError::BadMagic);
+        }
+
+        let version = data.u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let program_counter = data.u32()?;
+        let halt = data.bool()?;
+        let halt_behaviour = match data.u8()? {
+            0 => HaltBehaviour::ResetProgramCounter,
+            1 => HaltBehaviour::KeepProgramCounter,
+            value => return Err(corrupt(format!("unknown halt behaviour {}", value)))
+        };
+        let program_end = match data.u8()? {
+            0 => ProgramEndPolicy::Fault,
+            1 => ProgramEndPolicy::Halt,
+            value => return Err(corrupt(format!("unknown program end policy {}", value)))
+        };
+        let cycles = data.u64()?;
+
+        let mut registers = [0; REGISTER_COUNT];
+        registers.copy_from_slice(data.bytes(REGISTER_COUNT)?);
+
+        let memory_length = data.u32()? as usize;
+        if memory_length != USABLE_MEMORY_SIZE {
+            return Err(corrupt(format!("memory is {} bytes, expected {}", memory_length, USABLE_MEMORY_SIZE)));
+        }
+
+        let memory = data.bytes(memory_length)?.to_vec();
+
+        let stack_size = data.u32()?;
+        let stack_policy = match data.u8()? {
+            0 => StackPolicy::Wrap,
+            1 => StackPolicy::Trap,
+            2 => StackPolicy::Grow,
+            value => return Err(corrupt(format!("unknown stack policy {}", value)))
+        };
+
+        check_config(Stack::check_size(stack_size, stack_policy))?;
+
+        let mut stack = Stack::with_policy(stack_size, stack_policy);
+        let stack_length = data.u32()?;
+        if stack_length > stack.max_size() && stack_policy != StackPolicy::Grow {
+            return Err(corrupt(format!("stack holds {} entries, expected at most {}", stack_length, stack.max_size())));
+        }
+
+        for _ in 0..stack_length {
+            stack.push(data.u32()?).map_err(|fault| corrupt(fault.to_string()))?;
+        }
+
+        let high_water_mark = data.u32()? as usize;
+        if high_water_mark < stack.stack().len() {
+            return Err(corrupt(format!("stack high water mark {} is below its length {}", high_water_mark, stack_length)));
+        }
+
+        stack.set_high_water_mark(high_water_mark);
+
+        let zero_flag = data.bool()?;
+        let carry_flag = data.bool()?;
+
+        let width = data.u32()? as usize;
+        let height = data.u32()? as usize;
+        check_config(Screen::check_size(width, height))?;
+
+        let mut screen = Screen::new(width, height);
+        screen.x = data.i64()? as isize;
+        screen.y = data.i64()? as isize;
+
+        let screen_length = data.u32()? as usize;
+        let buffer = data.bytes(screen_length)?;
+        let image = data.bytes(screen_length)?;
+        if !screen.set_contents(buffer, image) {
+            return Err(corrupt(format!("screen data is {} bytes, expected {}", screen_length, screen.image().len())));
+        }
+
+        let character_capacity = data.u32()? as usize;
+        check_config(CharacterDisplay::check_capacity(character_capacity))?;
+
+        let mut character_display = CharacterDisplay::new(character_capacity);
+        let buffer = data.string()?;
+        let characters = data.string()?;
+        character_display.set_contents(&buffer, &characters);
+        character_display.check_contents().map_err(corrupt)?;
+
+        let mut number_display = NumberDisplay::new();
+        number_display.signed = data.bool()?;
+        number_display.set_value(data.u8()?);
+
+        let mut controller = Controller::new();
+        controller.set_binary(data.u8()?);
+
+        let mut ports = [None; PORTS];
+        for mapping in &mut ports {
+            *mapping = data.port()?;
+        }
+
+        let random_length = data.u32()? as usize;
+        let random = data.bytes(random_length)?.to_vec();
+
+        let program_length = data.u32()? as usize;
+        let mut program = Vec::with_capacity(program_length.min(bytes.len()));
+        for _ in 0..program_length {
+            program.push(data.instruction()?);
+        }
+
+        if data.position != bytes.len() {
+            return Err(corrupt(format!("{} trailing bytes", bytes.len() - data.position)));
+        }
+
+        Ok(Self {
+            program_counter,
+            halt,
+            halt_behaviour,
+            program_end,
+            cycles,
+
+            registers,
+            memory,
+            stack,
+
+            zero_flag,
+            carry_flag,
+
+            screen,
+            character_display,
+            number_display,
+            controller,
+            ports,
+
+            random,
+            program
+        })
+    }
+}
+
+impl Machine {
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            program_counter: self.program_counter,
+            halt: self.halt,
+            halt_behaviour: self.halt_behaviour,
+            program_end: self.program_end,
+            cycles: self.cycles,
+
+            registers: self.registers,
+            memory: self.memory.to_vec(),
+            stack: self.stack.clone(),
+
+            zero_flag: self.zero_flag,
+            carry_flag: self.carry_flag,
+
+            screen: self.screen.clone(),
+            character_display: self.character_display.clone(),
+            number_display: self.number_display.clone(),
+            controller: self.controller.clone(),
+            ports: std::array::from_fn(|port| self.bus.port(port)),
+
+            random: self.random.state(),
+            program: self.instructions.iter().map(InstructionState::from).collect()
+        }
+    }
+
+    pub fn restore(&mut self, state: MachineState) -> Result<(), SaveStateError> {
+        if state.memory.len() != USABLE_MEMORY_SIZE {
+            return Err(corrupt(format!("memory is {} bytes, expected {}", state.memory.len(), USABLE_MEMORY_SIZE)));
+        }
+
+        check_config(Stack::check_size(state.stack.max_size(), state.stack.policy()))?;
+        check_config(Screen::check_size(state.screen.width(), state.screen.height()))?;
+        check_config(CharacterDisplay::check_capacity(state.character_display.capacity()))?;
+        state.character_display.check_contents().map_err(corrupt)?;
+
+        for mapping in state.ports.iter().flatten() {
+            if let DeviceId::Custom(index) = mapping.device && self.bus.device(index).is_none() {
+                return Err(corrupt(format!("port mapped to custom device {} which is not attached", index)));
+            }
+        }
+
+        let program = state.program.iter().map(InstructionState::to_instruction).collect::<Result<Vec<_>, _>>()?;
+
+        if !state.random.is_empty() && !self.random.set_state(&state.random) {
+            return Err(corrupt("random state rejected by the random source".to_string()));
+        }
+
+        self.program_counter = state.program_counter;
+        self.halt = state.halt;
+        self.halt_behaviour = state.halt_behaviour;
+        self.program_end = state.program_end;
+        self.cycles = state.cycles;
+
+        self.registers = state.registers;
+        self.memory.copy_from_slice(&state.memory);
+        self.stack.restore(state.stack);
+
+        self.registers_updated = true;
+        self.memory_updated = true;
+
+        self.zero_flag = state.zero_flag;
+        self.carry_flag = state.carry_flag;
+
+        self.flags_updated = true;
+
+        self.screen.restore(state.screen);
+        self.character_display.restore(state.character_display);
+        self.number_display.restore(state.number_display);
+        self.controller = state.controller;
+
+        for (port, mapping) in state.ports.iter().enumerate() {
+            match mapping {
+                Some(mapping) => {
+                    self.bus.map(port, mapping.device, mapping.register);
+                },
+                None => {
+                    self.bus.unmap(port);
+                }
+            }
+        }
+
+        let same_program = self.instructions.iter().map(InstructionState::from).eq(state.program.iter().cloned());
+        let source_map = self.source_map.take().filter(|_| same_program);
+
+        self.set_instructions(program);
+        self.source_map = source_map;
+
+        if let Some(history) = &mut self.history {
+            history.restart(self.cycles);
+        }
+
+        Ok(())
+    }
+
+    pub fn from_state(state: MachineState) -> Result<Self, SaveStateError> {
+        let mut machine = Machine::new();
+        machine.restore(state)?;
+
+        Ok(machine)
+    }
+
+    pub fn save_state(&self, writer: &mut dyn Write) -> Result<(), SaveStateError> {
+        self.snapshot().write_to(writer)?;
+        Ok(())
+    }
+
+    pub fn load_state(&mut self, reader: &mut dyn Read) -> Result<(), SaveStateError> {
+        let state = MachineState::read_from(reader)?;
+        self.restore(state)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Machine {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Machine {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = MachineState::deserialize(deserializer)?;
+        Machine::from_state(state).map_err(serde::de::Error::custom)
+    }
+}
+
+fn corrupt(message: String) -> SaveStateError {
+    SaveStateError::Corrupt(message)
+}
+
+struct StateWriter {
+    data: Vec<u8>
+}
+
+impl StateWriter {
+    fn new() -> Self {
+        Self {
+            data: Vec::new()
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    fn location(&mut self, location: &LocationState) {
+        match location {
+            LocationState::Address(address) => {
+                self.u8(0);
+                self.u32(*address);
+            },
+            LocationState::Offset(offset) => {
+                self.u8(1);
+                self.i32(*offset);
+            },
+            LocationState::Label(label) => {
+                self.u8(2);
+                self.string(label);
+            }
+        }
+    }
+
+    fn registers(&mut self, registers: &[u8]) {
+        self.bytes(registers);
+    }
+
+    fn port(&mut self, mapping: &Option<PortMapping>) {
+        let Some(mapping) = mapping else {
+            self.u8(0);
+            return;
+        };
+
+        match mapping.device {
+            DeviceId::Screen => self.u8(1),
+            DeviceId::CharacterDisplay => self.u8(2),
+            DeviceId::NumberDisplay => self.u8(3),
+            DeviceId::Random => self.u8(4),
+            DeviceId::Controller => self.u8(5),
+            DeviceId::Custom(index) => {
+                self.u8(6);
+                self.u32(index as u32);
+            }
+        }
+        self.u32(mapping.register as u32);
+    }
+
+    fn instruction(&mut self, instruction: &InstructionState) {
+        match instruction {
+            InstructionState::NoOperation => self.u8(0),
+            InstructionState::Halt => self.u8(1),
+            InstructionState::Addition(a, b, c) => {
+                self.u8(2);
+                self.registers(&[*a, *b, *c]);
+            },
+            InstructionState::Subtraction(a, b, c) => {
+                self.u8(3);
+                self.registers(&[*a, *b, *c]);
+            },
+            InstructionState::BitwiseNOR(a, b, c) => {
+                self.u8(4);
+                self.registers(&[*a, *b, *c]);
+            },
+            InstructionState::BitwiseAND(a, b, c) => {
+                self.u8(5);
+                self.registers(&[*a, *b, *c]);
+            },
+            InstructionState::BitwiseXOR(a, b, c) => {
+                self.u8(6);
+                self.registers(&[*a, *b, *c]);
+            },
+            InstructionState::RightShift(a, c) => {
+                self.u8(7);
+                self.registers(&[*a, *c]);
+            },
+            InstructionState::LoadImmediate(a, immediate) => {
+                self.u8(8);
+                self.u8(*a);
+                self.i32(*immediate);
+            },
+            InstructionState::AddImmediate(a, immediate) => {
+                self.u8(9);
+                self.u8(*a);
+                self.i32(*immediate);
+            },
+            InstructionState::Jump(location) => {
+                self.u8(10);
+                self.location(location);
+            },
+            InstructionState::Branch(condition, location) => {
+                self.u8(11);
+                self.u8(match condition {
+                    ConditionState::Zero     => 0,
+                    ConditionState::NotZero  => 1,
+                    ConditionState::Carry    => 2,
+                    ConditionState::NotCarry => 3
+                });
+                self.location(location);
+            },
+            InstructionState::Call(location) => {
+                self.u8(12);
+                self.location(location);
+            },
+            InstructionState::Return => self.u8(13),
+            InstructionState::MemoryLoad(a, b, offset) => {
+                self.u8(14);
+                self.registers(&[*a, *b]);
+                self.i32(*offset);
+            },
+            InstructionState::MemoryStore(a, b, offset) => {
+                self.u8(15);
+                self.registers(&[*a, *b]);
+                self.i32(*offset);
+            }
+        }
+    }
+}
+
+struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.position.checked_add(length).filter(|&end| end <= self.data.len());
+
+        match end {
+            Some(end) => {
+                let bytes = &self.data[self.position..end];
+                self.position = end;
+
+                Ok(bytes)
+            },
+            None => Err(corrupt(format!("unexpected end of file at byte {}", self.position)))
+        }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(corrupt(format!("invalid boolean {} at byte {}", value, self.position - 1)))
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, SaveStateError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, SaveStateError> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, SaveStateError> {
+        let length = self.u32()? as usize;
+        let bytes = self.bytes(length)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|error| corrupt(error.to_string()))
+    }
+
+    fn register(&mut self) -> Result<u8, SaveStateError> {
+        let register = self.u8()?;
+        if register as usize >= REGISTER_COUNT {
+            return Err(corrupt(format!("invalid register {}", register)));
+        }
+
+        Ok(register)
+    }
+
+    fn location(&mut self) -> Result<LocationState, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(LocationState::Address(self.u32()?)),
+            1 => Ok(LocationState::Offset(self.i32()?)),
+            2 => Ok(LocationState::Label(self.string()?)),
+            tag => Err(corrupt(format!("unknown location tag {}", tag)))
+        }
+    }
+
+    fn port(&mut self) -> Result<Option<PortMapping>, SaveStateError> {
+        let device = match self.u8()? {
+            0 => return Ok(None),
+            1 => DeviceId::Screen,
+            2 => DeviceId::CharacterDisplay,
+            3 => DeviceId::NumberDisplay,
+            4 => DeviceId::Random,
+            5 => DeviceId::Controller,
+            6 => DeviceId::Custom(self.u32()? as usize),
+            tag => return Err(corrupt(format!("unknown device tag {}", tag)))
+        };
+
+        Ok(Some(PortMapping::new(device, self.u32()? as usize)))
+    }
+
+    fn instruction(&mut self) -> Result<InstructionState, SaveStateError> {
+        let instruction = match self.u8()? {
+            0 => InstructionState::NoOperation,
+            1 => InstructionState::Halt,
+            2 => InstructionState::Addition(self.register()?, self.register()?, self.register()?),
+            3 => InstructionState::Subtraction(self.register()?, self.register()?, self.register()?),
+            4 => InstructionState::BitwiseNOR(self.register()?, self.register()?, self.register()?),
+            5 => InstructionState::BitwiseAND(self.register()?, self.register()?, self.register()?),
+            6 => InstructionState::BitwiseXOR(self.register()?, self.register()?, self.register()?),
+            7 => InstructionState::RightShift(self.register()?, self.register()?),
+            8 => InstructionState::LoadImmediate(self.register()?, self.i32()?),
+            9 => InstructionState::AddImmediate(self.register()?, self.i32()?),
+            10 => InstructionState::Jump(self.location()?),
+            11 => {
+                let condition = match self.u8()? {
+                    0 => ConditionState::Zero,
+                    1 => ConditionState::NotZero,
+                    2 => ConditionState::Carry,
+                    3 => ConditionState::NotCarry,
+                    value => return Err(corrupt(format!("unknown condition {}", value)))
+                };
+
+                InstructionState::Branch(condition, self.location()?)
+            },
+            12 => InstructionState::Call(self.location()?),
+            13 => InstructionState::Return,
+            14 => InstructionState::MemoryLoad(self.register()?, self.register()?, self.i32()?),
+            15 => InstructionState::MemoryStore(self.register()?, self.register()?, self.i32()?),
+            opcode => return Err(corrupt(format!("unknown opcode {}", opcode)))
+        };
+
+        instruction.validate()?;
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::random::SeededRandom;
+    use crate::machine::config::MachineConfig;
+    use crate::test_support::Latch;
+
+    fn machine() -> Machine {
+        let mut machine = MachineConfig::new()
+            .stack_policy(StackPolicy::Trap)
+            .screen_size(8, 4)
+            .halt_behaviour(HaltBehaviour::KeepProgramCounter)
+            .program_end(ProgramEndPolicy::Halt)
+            .random(Box::new(SeededRandom::new(3)))
+            .build()
+            .unwrap();
+
+        machine.set_instructions(vec![
+            Instruction::LoadImmediate(Register::new(1), Immediate::new(-5)),
+            Instruction::MemoryStore(Register::new(1), Register::new(2), Offset::new(-8)),
+            Instruction::Branch(Condition::Carry, Location::Address(Address::new(address::MAX_VALUE))),
+            Instruction::Call(Location::Offset(Offset::new(2))),
+            Instruction::Jump(Location::Label("loop".into())),
+            Instruction::Halt
+        ]);
+        machine.bus_mut().unmap(14);
+        machine.bus_mut().map(0, DeviceId::NumberDisplay, 1);
+        machine.memory_mut()[3] = 9;
+        machine.stack_mut().push(4).unwrap();
+        machine.screen_mut().set_pix(true);
+        machine.screen_mut().push_buffer();
+
+        machine
+    }
+
+    fn save(machine: &Machine) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        machine.save_state(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn load(bytes: &[u8]) -> Result<Machine, SaveStateError> {
+        let mut machine = Machine::new();
+        machine.load_state(&mut &bytes[..])?;
+        Ok(machine)
+    }
+
+    fn corrupt_message(bytes: &[u8]) -> String {
+        match load(bytes) {
+            Err(SaveStateError::Corrupt(message)) => message,
+            Err(error) => panic!("expected a corrupt state, got {}", error),
+            Ok(_) => panic!("expected a corrupt state")
+        }
+    }
+
+    fn instruction_state(instruction: InstructionState) -> Vec<u8> {
+        let mut state = machine().snapshot();
+        state.program = vec![instruction];
+
+        let mut bytes = Vec::new();
+        state.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_the_machine() {
+        let original = machine();
+        let bytes = save(&original);
+        let restored = load(&bytes).unwrap();
+
+        assert_eq!(save(&restored), bytes);
+        assert_eq!(restored.memory()[3], 9);
+        assert_eq!(restored.stack().stack(), &[4]);
+        assert_eq!(restored.stack().policy(), StackPolicy::Trap);
+        assert_eq!(restored.screen().width(), 8);
+        assert!(restored.screen().pixel(0, 0));
+        assert_eq!(restored.halt_behaviour(), HaltBehaviour::KeepProgramCounter);
+        assert_eq!(restored.program_end(), ProgramEndPolicy::Halt);
+        assert_eq!(restored.bus().port(0), Some(PortMapping::new(DeviceId::NumberDisplay, 1)));
+        assert_eq!(restored.bus().port(14), None);
+        assert_eq!(restored.instructions(), original.instructions());
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let bytes = save(&machine());
+
+        for length in 0..bytes.len() {
+            assert!(load(&bytes[..length]).is_err(), "accepted {} of {} bytes", length, bytes.len());
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bytes = save(&machine());
+        bytes[0] = b'X';
+        assert!(matches!(load(&bytes), Err(SaveStateError::BadMagic)));
+
+        let mut bytes = save(&machine());
+        bytes[4..6].copy_from_slice(&(SAVE_STATE_VERSION + 1).to_le_bytes());
+        assert!(matches!(load(&bytes), Err(SaveStateError::UnsupportedVersion(_))));
+
+        let mut bytes = save(&machine());
+        bytes.push(0);
+        assert_eq!(corrupt_message(&bytes), "1 trailing bytes");
+    }
+
+    #[test]
+    fn rejects_out_of_range_operands() {
+        let cases = [
+            (InstructionState::Addition(0, 16, 0), "invalid register 16"),
+            (InstructionState::LoadImmediate(1, 256), "immediate 256 is out of range, expected -128-255"),
+            (InstructionState::AddImmediate(1, -129), "immediate -129 is out of range, expected -128-255"),
+            (InstructionState::MemoryLoad(1, 2, 8), "offset 8 is out of range, expected -8-7"),
+            (InstructionState::Jump(LocationState::Address(1024)), "address 1024 is out of range, expected at most 1023"),
+            (InstructionState::Call(LocationState::Offset(-9)), "offset -9 is out of range, expected -8-7")
+        ];
+
+        for (instruction, message) in cases {
+            assert!(instruction.to_instruction().is_err());
+            assert_eq!(corrupt_message(&instruction_state(instruction)), message);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_components() {
+        let mut state = machine().snapshot();
+        state.screen = Screen::new(257, 1);
+        assert!(Machine::from_state(state).is_err());
+
+        let mut bytes = Vec::new();
+        let mut state = machine().snapshot();
+        state.stack = Stack::new(0);
+        state.write_to(&mut bytes).unwrap();
+        assert_eq!(corrupt_message(&bytes), ConfigError::StackSize(0).to_string());
+
+        let mut bytes = save(&machine());
+        let width = bytes.windows(8).position(|window| window == [8, 0, 0, 0, 4, 0, 0, 0]).unwrap();
+        bytes[width..width + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(corrupt_message(&bytes), ConfigError::ScreenSize(u32::MAX as usize, 4).to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_signed_immediates() {
+        use serde_test::{assert_tokens, Token};
+
+        let instruction = InstructionState::from(&Instruction::LoadImmediate(Register::new(1), Immediate::new(-5)));
+        assert_tokens(&instruction, &[
+            Token::TupleVariant { name: "InstructionState", variant: "LoadImmediate", len: 2 },
+            Token::U8(1),
+            Token::I32(-5),
+            Token::TupleVariantEnd
+        ]);
+    }
+
+    #[test]
+    fn rejects_overfull_character_displays() {
+        let mut state = machine().snapshot();
+        state.character_display = CharacterDisplay::new(2);
+        state.character_display.set_contents("ABC", "");
+
+        let mut bytes = Vec::new();
+        state.write_to(&mut bytes).unwrap();
+        assert_eq!(corrupt_message(&bytes), "Character buffer holds 3 characters, expected at most 2");
+
+        assert!(matches!(Machine::from_state(state), Err(SaveStateError::Corrupt(_))));
+    }
+
+    #[test]
+    fn rejects_unattached_custom_devices() {
+        let mut original = machine();
+        let device = original.bus_mut().attach(Box::new(Latch(0)));
+        original.bus_mut().map(1, device, 0);
+        let bytes = save(&original);
+
+        assert_eq!(corrupt_message(&bytes), "port mapped to custom device 0 which is not attached");
+
+        let mut restored = Machine::new();
+        restored.bus_mut().attach(Box::new(Latch(0)));
+        restored.load_state(&mut &bytes[..]).unwrap();
+        assert_eq!(restored.bus().port(1), Some(PortMapping::new(device, 0)));
+    }
+}
\ No newline at end of file
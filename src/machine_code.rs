@@ -0,0 +1,489 @@
+use crate::disassembler::format_location;
+use batpu_assembly::components::address::{self, Address};
+use batpu_assembly::components::condition::Condition;
+use batpu_assembly::components::immediate::Immediate;
+use batpu_assembly::components::location::Location;
+use batpu_assembly::components::offset::Offset;
+use batpu_assembly::components::register::Register;
+use batpu_assembly::instruction::Instruction;
+use batpu_assembly::InstructionVec;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+pub const WORD_BITS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineCodeError {
+    InvalidLength {
+        line: usize,
+        length: usize
+    },
+    InvalidDigit {
+        line: usize,
+        column: usize,
+        character: char
+    },
+    OddByteCount(usize),
+    ProgramTooLong(usize)
+}
+
+impl Display for MachineCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineCodeError::InvalidLength { line, length } => {
+                write!(f, "Line {}: word has {} digits, expected {}", line, length, WORD_BITS)
+            },
+            MachineCodeError::InvalidDigit { line, column, character } => {
+                write!(f, "Line {}, column {}: invalid digit '{}', expected 0 or 1", line, column, character)
+            },
+            MachineCodeError::OddByteCount(count) => {
+                write!(f, "Binary has {} bytes, expected an even number", count)
+            },
+            MachineCodeError::ProgramTooLong(length) => {
+                write!(f, "Program has {} instructions, expected at most {}", length, address::MAX_POSSIBLE_COUNT)
+            }
+        }
+    }
+}
+
+impl Error for MachineCodeError {}
+
+pub fn decode_word(word: u16) -> Instruction {
+    let a = Register::new(((word >> 8) & 0xF) as _);
+    let b = Register::new(((word >> 4) & 0xF) as _);
+    let c = Register::new((word & 0xF) as _);
+
+    let immediate = Immediate::new((word & 0xFF) as _);
+    let location = Location::Address(Address::new((word & 0x3FF) as _));
+    let offset = Offset::new((((word & 0xF) as i8) << 4 >> 4) as _);
+
+    match word >> 12 {
+        0x0 => Instruction::NoOperation,
+        0x1 => Instruction::Halt,
+        0x2 => Instruction::Addition(a, b, c),
+        0x3 => Instruction::Subtraction(a, b, c),
+        0x4 => Instruction::BitwiseNOR(a, b, c),
+        0x5 => Instruction::BitwiseAND(a, b, c),
+        0x6 => Instruction::BitwiseXOR(a, b, c),
+        0x7 => Instruction::RightShift(a, c),
+        0x8 => Instruction::LoadImmediate(a, immediate),
+        0x9 => Instruction::AddImmediate(a, immediate),
+        0xA => Instruction::Jump(location),
+        0xB => {
+            let condition = match (word >> 10) & 0b11 {
+                0b00 => Condition::Zero,
+                0b01 => Condition::NotZero,
+                0b10 => Condition::Carry,
+                _    => Condition::NotCarry
+            };
+
+            Instruction::Branch(condition, location)
+        },
+        0xC => Instruction::Call(location),
+        0xD => Instruction::Return,
+        0xE => Instruction::MemoryLoad(a, b, offset),
+        _   => Instruction::MemoryStore(a, b, offset)
+    }
+}
+
+pub fn parse_text(source: &str) -> Result<InstructionVec, Vec<MachineCodeError>> {
+    let mut instructions = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+
+        let word = line.trim();
+        if word.is_empty() {
+            continue;
+        }
+
+        let length = word.chars().count();
+        if length != WORD_BITS {
+            errors.push(MachineCodeError::InvalidLength {
+                line: line_number,
+                length
+            });
+            continue;
+        }
+
+        let mut value = 0u16;
+        let mut valid = true;
+        let indent = line.chars().take_while(|character| character.is_whitespace()).count();
+
+        for (column, character) in word.chars().enumerate() {
+            let bit = match character {
+                '0' => 0,
+                '1' => 1,
+                _ => {
+                    errors.push(MachineCodeError::InvalidDigit {
+                        line: line_number,
+                        column: indent + column + 1,
+                        character
+                    });
+
+                    valid = false;
+                    break;
+                }
+            };
+
+            value = (value << 1) | bit;
+        }
+
+        if valid {
+            instructions.push(decode_word(value));
+        }
+    }
+
+    if instructions.len() > address::MAX_POSSIBLE_COUNT as usize {
+        errors.push(MachineCodeError::ProgramTooLong(instructions.len()));
+    }
+
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn parse_binary(bytes: &[u8]) -> Result<InstructionVec, MachineCodeError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(MachineCodeError::OddByteCount(bytes.len()));
+    }
+
+    let length = bytes.len() / 2;
+    if length > address::MAX_POSSIBLE_COUNT as usize {
+        return Err(MachineCodeError::ProgramTooLong(length));
+    }
+
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|word| decode_word(u16::from_be_bytes([word[0], word[1]])))
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeErrorKind {
+    UnresolvedLocation(String),
+    AddressOutOfRange(u32),
+    ImmediateOutOfRange(i64),
+    OffsetOutOfRange(i32)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeError {
+    pub index: usize,
+    pub kind: EncodeErrorKind
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            EncodeErrorKind::UnresolvedLocation(location) => {
+                write!(f, "Instruction {}: unresolved location {}, link the program first", self.index, location)
+            },
+            EncodeErrorKind::AddressOutOfRange(address) => {
+                write!(f, "Instruction {}: address {} out of range, expected 0-{}", self.index, address, address::MAX_VALUE)
+            },
+            EncodeErrorKind::ImmediateOutOfRange(immediate) => {
+                write!(f, "Instruction {}: immediate {} does not fit in 8 bits", self.index, immediate)
+            },
+            EncodeErrorKind::OffsetOutOfRange(offset) => {
+                write!(f, "Instruction {}: offset {} out of range, expected -8-7", self.index, offset)
+            }
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Text,
+    IntelHex,
+    Binary
+}
+
+impl ExportFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "mc" => Some(ExportFormat::Text),
+            "hex" | "ihex" => Some(ExportFormat::IntelHex),
+            "bin" => Some(ExportFormat::Binary),
+            _ => None
+        }
+    }
+}
+
+pub fn encode_word(instruction: &Instruction) -> Result<u16, EncodeErrorKind> {
+    let word = match instruction {
+        Instruction::NoOperation => 0x0000,
+        Instruction::Halt => 0x1000,
+        Instruction::Addition(a, b, c) => 0x2000 | registers(a, b, c),
+        Instruction::Subtraction(a, b, c) => 0x3000 | registers(a, b, c),
+        Instruction::BitwiseNOR(a, b, c) => 0x4000 | registers(a, b, c),
+        Instruction::BitwiseAND(a, b, c) => 0x5000 | registers(a, b, c),
+        Instruction::BitwiseXOR(a, b, c) => 0x6000 | registers(a, b, c),
+        Instruction::RightShift(a, c) => 0x7000 | register(a) << 8 | register(c),
+        Instruction::LoadImmediate(a, immediate) => 0x8000 | register(a) << 8 | encode_immediate(immediate)?,
+        Instruction::AddImmediate(a, immediate) => 0x9000 | register(a) << 8 | encode_immediate(immediate)?,
+        Instruction::Jump(location) => 0xA000 | encode_location(location)?,
+        Instruction::Branch(condition, location) => {
+            let condition = match condition {
+                Condition::Zero     => 0b00,
+                Condition::NotZero  => 0b01,
+                Condition::Carry    => 0b10,
+                Condition::NotCarry => 0b11
+            };
+
+            0xB000 | condition << 10 | encode_location(location)?
+        },
+        Instruction::Call(location) => 0xC000 | encode_location(location)?,
+        Instruction::Return => 0xD000,
+        Instruction::MemoryLoad(a, b, offset) => 0xE000 | register(a) << 8 | register(b) << 4 | encode_offset(offset)?,
+        Instruction::MemoryStore(a, b, offset) => 0xF000 | register(a) << 8 | register(b) << 4 | encode_offset(offset)?
+    };
+
+    Ok(word)
+}
+
+pub fn encode(instructions: &[Instruction]) -> Result<Vec<u16>, Vec<EncodeError>> {
+    let mut words = Vec::with_capacity(instructions.len());
+    let mut errors = Vec::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        match encode_word(instruction) {
+            Ok(word) => words.push(word),
+            Err(kind) => errors.push(EncodeError {
+                index,
+                kind
+            })
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(words)
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn export(instructions: &[Instruction], format: ExportFormat) -> Result<Vec<u8>, Vec<EncodeError>> {
+    let words = encode(instructions)?;
+
+    Ok(match format {
+        ExportFormat::Text => to_text(&words).into_bytes(),
+        ExportFormat::IntelHex => to_intel_hex(&words).into_bytes(),
+        ExportFormat::Binary => to_binary(&words)
+    })
+}
+
+pub fn to_text(words: &[u16]) -> String {
+    let mut text = String::with_capacity(words.len() * (WORD_BITS + 1));
+
+    for word in words {
+        text.push_str(&format!("{:016b}\n", word));
+    }
+
+    text
+}
+
+pub fn to_binary(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+pub fn to_intel_hex(words: &[u16]) -> String {
+    let bytes = to_binary(words);
+    let mut text = String::new();
+
+    for (record, data) in bytes.chunks(16).enumerate() {
+        let address = (record * 16) as u16;
+        text.push_str(&hex_record(address, 0x00, data));
+    }
+
+    text.push_str(&hex_record(0, 0x01, &[]));
+    text
+}
+
+fn hex_record(address: u16, kind: u8, data: &[u8]) -> String {
+    let [high, low] = address.to_be_bytes();
+    let mut checksum = (data.len() as u8).wrapping_add(high).wrapping_add(low).wrapping_add(kind);
+
+    let mut record = format!(":{:02X}{:04X}{:02X}", data.len(), address, kind);
+    for &byte in data {
+        record.push_str(&format!("{:02X}", byte));
+        checksum = checksum.wrapping_add(byte);
+    }
+
+    record.push_str(&format!("{:02X}\n", checksum.wrapping_neg()));
+    record
+}
+
+fn register(register: &Register) -> u16 {
+    register.register() as u16 & 0xF
+}
+
+fn registers(a: &Register, b: &Register, c: &Register) -> u16 {
+    register(a) << 8 | register(b) << 4 | register(c)
+}
+
+fn encode_immediate(immediate: &Immediate) -> Result<u16, EncodeErrorKind> {
+    let value = immediate.immediate() as i64;
+
+    match value {
+        0..=255 => Ok(value as u16),
+        -128..=-1 => Ok((value as u8) as u16),
+        _ => Err(EncodeErrorKind::ImmediateOutOfRange(value))
+    }
+}
+
+fn encode_location(location: &Location) -> Result<u16, EncodeErrorKind> {
+    match location {
+        Location::Address(address) if address.address() <= address::MAX_VALUE => Ok(address.address() as u16),
+        Location::Address(address) => Err(EncodeErrorKind::AddressOutOfRange(address.address())),
+        _ => Err(EncodeErrorKind::UnresolvedLocation(format_location(location)))
+    }
+}
+
+fn encode_offset(offset: &Offset) -> Result<u16, EncodeErrorKind> {
+    match offset.offset() {
+        offset @ -8..=7 => Ok(offset as u16 & 0xF),
+        offset => Err(EncodeErrorKind::OffsetOutOfRange(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_branch_conditions_from_bits_11_and_10() {
+        let conditions = [Condition::Zero, Condition::NotZero, Condition::Carry, Condition::NotCarry];
+
+        for (bits, condition) in conditions.into_iter().enumerate() {
+            let word = 0xB000 | (bits as u16) << 10 | 0x2A5;
+            assert_eq!(decode_word(word), Instruction::Branch(condition, Location::Address(Address::new(0x2A5))));
+        }
+    }
+
+    #[test]
+    fn sign_extends_memory_offsets() {
+        let cases = [(0x0, 0), (0x7, 7), (0x8, -8), (0xF, -1)];
+
+        for (bits, offset) in cases {
+            assert_eq!(decode_word(0xE120 | bits), Instruction::MemoryLoad(Register::new(1), Register::new(2), Offset::new(offset)));
+            assert_eq!(decode_word(0xF340 | bits), Instruction::MemoryStore(Register::new(3), Register::new(4), Offset::new(offset)));
+        }
+    }
+
+    #[test]
+    fn decodes_register_and_immediate_fields() {
+        assert_eq!(decode_word(0x2123), Instruction::Addition(Register::new(1), Register::new(2), Register::new(3)));
+        assert_eq!(decode_word(0x7F0E), Instruction::RightShift(Register::new(15), Register::new(14)));
+        assert_eq!(decode_word(0x85FF), Instruction::LoadImmediate(Register::new(5), Immediate::new(255)));
+        assert_eq!(decode_word(0xC3FF), Instruction::Call(Location::Address(Address::new(1023))));
+    }
+
+    #[test]
+    fn reports_bad_digits() {
+        let errors = parse_text("0001000000000000\n  00010000000x0000\n").unwrap_err();
+
+        assert_eq!(errors, vec![MachineCodeError::InvalidDigit {
+            line: 2,
+            column: 14,
+            character: 'x'
+        }]);
+
+        let errors = parse_text("\u{3000}\t0001x00000000000\n").unwrap_err();
+
+        assert_eq!(errors, vec![MachineCodeError::InvalidDigit {
+            line: 1,
+            column: 7,
+            character: 'x'
+        }]);
+    }
+
+    #[test]
+    fn reports_bad_lengths() {
+        let errors = parse_text("000100000000000\n0001000000000000\n00010000000000000\n").unwrap_err();
+
+        assert_eq!(errors, vec![
+            MachineCodeError::InvalidLength {
+                line: 1,
+                length: 15
+            },
+            MachineCodeError::InvalidLength {
+                line: 3,
+                length: 17
+            }
+        ]);
+    }
+
+    #[test]
+    fn skips_blank_lines() {
+        let instructions = parse_text("\n0001000000000000\n   \n1101000000000000\n").unwrap();
+        assert_eq!(instructions, vec![Instruction::Halt, Instruction::Return]);
+    }
+
+    #[test]
+    fn rejects_odd_binaries() {
+        assert_eq!(parse_binary(&[0x10, 0x00, 0xD0]), Err(MachineCodeError::OddByteCount(3)));
+        assert_eq!(parse_binary(&[0x10, 0x00, 0xD0, 0x00]), Ok(vec![Instruction::Halt, Instruction::Return]));
+    }
+
+    #[test]
+    fn encoding_inverts_decoding_for_every_opcode() {
+        for word in 0..=u16::MAX {
+            let unused = match word >> 12 {
+                0x0 | 0x1 | 0xD => 0x0FFF,
+                0x7 => 0x00F0,
+                0xA | 0xC => 0x0C00,
+                _ => 0x0000
+            };
+
+            assert_eq!(encode_word(&decode_word(word)), Ok(word & !unused), "word {:016b}", word);
+        }
+    }
+
+    fn program() -> Vec<Instruction> {
+        vec![
+            Instruction::LoadImmediate(Register::new(1), Immediate::new(10)),
+            Instruction::LoadImmediate(Register::new(2), Immediate::new(-1)),
+            Instruction::Addition(Register::new(1), Register::new(2), Register::new(3)),
+            Instruction::Branch(Condition::NotZero, Location::Address(Address::new(6))),
+            Instruction::MemoryLoad(Register::new(1), Register::new(2), Offset::new(-1)),
+            Instruction::MemoryStore(Register::new(3), Register::new(4), Offset::new(7)),
+            Instruction::Call(Location::Address(Address::new(1023))),
+            Instruction::RightShift(Register::new(5), Register::new(6)),
+            Instruction::Return,
+            Instruction::Halt
+        ]
+    }
+
+    #[test]
+    fn exports_intel_hex_matching_a_known_good_file() {
+        let exported = export(&program(), ExportFormat::IntelHex).unwrap();
+        assert_eq!(String::from_utf8(exported).unwrap(), include_str!("../tests/fixtures/export.hex"));
+    }
+
+    #[test]
+    fn intel_hex_records_sum_to_zero() {
+        let words = encode(&program()).unwrap();
+
+        for record in to_intel_hex(&words).lines() {
+            let bytes = (1..record.len())
+                .step_by(2)
+                .map(|index| u8::from_str_radix(&record[index..index + 2], 16).unwrap());
+
+            assert_eq!(bytes.fold(0u8, u8::wrapping_add), 0, "record {}", record);
+        }
+    }
+
+    #[test]
+    fn exports_text_and_binary() {
+        let program = [Instruction::LoadImmediate(Register::new(1), Immediate::new(10)), Instruction::Halt];
+
+        assert_eq!(export(&program, ExportFormat::Text).unwrap(), b"1000000100001010\n0001000000000000\n");
+        assert_eq!(export(&program, ExportFormat::Binary).unwrap(), vec![0x81, 0x0A, 0x10, 0x00]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,142 @@
+use crate::linker::{symbol_name, SymbolTable};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize
+}
+
+impl SourceLocation {
+    pub fn new(file: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            file: file.into(),
+            line,
+            column
+        }
+    }
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceMap {
+    locations: Vec<Option<SourceLocation>>,
+    labels: BTreeMap<String, u32>,
+    defines: BTreeMap<String, i64>
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self {
+            locations: Vec::new(),
+            labels: BTreeMap::new(),
+            defines: BTreeMap::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn set_location(&mut self, index: usize, location: SourceLocation) {
+        if index >= self.locations.len() {
+            self.locations.resize(index + 1, None);
+        }
+
+        self.locations[index] = Some(location);
+    }
+
+    pub fn push_location(&mut self, location: SourceLocation) {
+        self.locations.push(Some(location));
+    }
+
+    pub fn add_label(&mut self, name: impl Into<String>, address: u32) {
+        let name = name.into();
+        self.labels.insert(symbol_name(&name).to_string(), address);
+    }
+
+    pub fn add_define(&mut self, name: impl Into<String>, value: i64) {
+        self.defines.insert(name.into(), value);
+    }
+
+    pub fn location(&self, index: u32) -> Option<&SourceLocation> {
+        self.locations.get(index as usize)?.as_ref()
+    }
+
+    pub fn labels(&self) -> &BTreeMap<String, u32> {
+        &self.labels
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, i64> {
+        &self.defines
+    }
+
+    pub fn address_of(&self, label: &str) -> Option<u32> {
+        self.labels.get(symbol_name(label)).copied()
+    }
+
+    pub fn define(&self, name: &str) -> Option<i64> {
+        self.defines.get(name).copied()
+    }
+
+    pub fn labels_at(&self, address: u32) -> impl Iterator<Item = &str> {
+        self.labels
+            .iter()
+            .filter(move |&(_, &target)| target == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn enclosing_label(&self, address: u32) -> Option<(&str, u32)> {
+        self.labels
+            .iter()
+            .filter(|&(_, &target)| target <= address)
+            .max_by_key(|&(name, &target)| (target, std::cmp::Reverse(name)))
+            .map(|(name, &target)| (name.as_str(), target))
+    }
+
+    pub fn find_line(&self, file: &str, line: usize) -> Option<u32> {
+        self.locations
+            .iter()
+            .enumerate()
+            .filter_map(|(index, location)| Some((index, location.as_ref()?)))
+            .filter(|(_, location)| location.line >= line && file_matches(&location.file, file))
+            .min_by_key(|&(index, location)| (location.line, index))
+            .map(|(index, _)| index as u32)
+    }
+
+    pub fn symbols(&self) -> SymbolTable {
+        self.labels.iter().map(|(name, &address)| (name.clone(), address)).collect()
+    }
+
+    pub fn describe(&self, address: u32) -> String {
+        let label = match self.enclosing_label(address) {
+            Some((name, target)) if target == address => Some(format!("label {}", name)),
+            Some((name, target)) => Some(format!("{}+{}", name, address - target)),
+            None => None
+        };
+
+        match (self.location(address), label) {
+            (Some(location), Some(label)) => format!("{} ({})", location, label),
+            (Some(location), None) => location.to_string(),
+            (None, Some(label)) => format!("{} ({})", address, label),
+            (None, None) => address.to_string()
+        }
+    }
+}
+
+fn file_matches(path: &str, file: &str) -> bool {
+    path == file || path.ends_with(&format!("/{}", file)) || path.ends_with(&format!("\\{}", file))
+}
\ No newline at end of file
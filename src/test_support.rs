@@ -0,0 +1,50 @@
+use crate::bus::Device;
+use crate::machine::{Machine, Word, PORTS_ADDRESS};
+use batpu_assembly::components::immediate::Immediate;
+use batpu_assembly::components::offset::Offset;
+use batpu_assembly::components::register::Register;
+use batpu_assembly::instruction::Instruction;
+
+pub(crate) struct Latch(pub(crate) Word);
+
+impl Device for Latch {
+    fn read(&mut self, _register: usize) -> Word {
+        self.0
+    }
+
+    fn write(&mut self, _register: usize, value: Word) {
+        self.0 = value;
+    }
+}
+
+pub(crate) fn machine(instructions: Vec<Instruction>) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_instructions(instructions);
+    machine
+}
+
+pub(crate) fn assembled(source: &str) -> Machine {
+    let mut machine = Machine::new();
+    machine.load_source(source).unwrap();
+    machine
+}
+
+pub(crate) fn r(register: u32) -> Register {
+    Register::new(register)
+}
+
+pub(crate) fn ldi(register: u32, value: i32) -> Instruction {
+    Instruction::LoadImmediate(r(register), Immediate::new(value))
+}
+
+pub(crate) fn store(address: u32, value: u32) -> Instruction {
+    Instruction::MemoryStore(r(address), r(value), Offset::new(0))
+}
+
+pub(crate) fn load(address: u32, value: u32) -> Instruction {
+    Instruction::MemoryLoad(r(address), r(value), Offset::new(0))
+}
+
+pub(crate) fn port(port: usize) -> i32 {
+    (PORTS_ADDRESS + port) as i32
+}
\ No newline at end of file
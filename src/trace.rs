@@ -0,0 +1,397 @@
+use crate::disassembler::format_instruction;
+use crate::error::MachineError;
+use crate::machine::{Word, REGISTER_COUNT};
+use crate::machine_code::encode_word;
+use batpu_assembly::instruction::Instruction;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+
+pub const BINARY_MAGIC: &[u8; 4] = b"BPTR";
+pub const BINARY_VERSION: u8 = 2;
+
+pub const FLAG_ZERO_CHANGED: u8 = 0b0000_0001;
+pub const FLAG_ZERO: u8 = 0b0000_0010;
+pub const FLAG_CARRY_CHANGED: u8 = 0b0000_0100;
+pub const FLAG_CARRY: u8 = 0b0000_1000;
+pub const FLAG_FAULT: u8 = 0b0001_0000;
+pub const FLAG_NO_WORD: u8 = 0b0010_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Binary
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortAccess {
+    Read {
+        port: usize,
+        value: Word
+    },
+    Write {
+        port: usize,
+        value: Word
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub program_counter: u32,
+    pub instruction: Instruction,
+    pub source: Option<String>,
+    
+    pub registers: Vec<(usize, Word, Word)>,
+    pub memory: Vec<(usize, Word, Word)>,
+    
+    pub zero_flag: Option<bool>,
+    pub carry_flag: Option<bool>,
+    
+    pub ports: Vec<PortAccess>,
+    pub fault: Option<MachineError>
+}
+
+impl TraceEntry {
+    pub fn write_binary(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut data = Vec::with_capacity(16 + 2 * (self.registers.len() + self.memory.len() + self.ports.len()));
+        let word = encode_word(&self.instruction).ok();
+        
+        data.extend_from_slice(&self.cycle.to_le_bytes());
+        data.extend_from_slice(&(self.program_counter as u16).to_le_bytes());
+        data.extend_from_slice(&word.unwrap_or(0).to_le_bytes());
+        
+        data.push(self.registers.len() as u8);
+        for &(register, _, new) in &self.registers {
+            data.push(register as u8);
+            data.push(new);
+        }
+        
+        data.push(self.memory.len() as u8);
+        for &(address, _, new) in &self.memory {
+            data.push(address as u8);
+            data.push(new);
+        }
+        
+        let mut flags = 0;
+        if let Some(zero_flag) = self.zero_flag {
+            flags |= FLAG_ZERO_CHANGED | if zero_flag { FLAG_ZERO } else { 0 };
+        }
+        if let Some(carry_flag) = self.carry_flag {
+            flags |= FLAG_CARRY_CHANGED | if carry_flag { FLAG_CARRY } else { 0 };
+        }
+        if self.fault.is_some() {
+            flags |= FLAG_FAULT;
+        }
+        if word.is_none() {
+            flags |= FLAG_NO_WORD;
+        }
+        data.push(flags);
+        
+        data.push(self.ports.len() as u8);
+        for access in &self.ports {
+            match *access {
+                PortAccess::Read { port, value } => {
+                    data.push(port as u8);
+                    data.push(value);
+                },
+                PortAccess::Write { port, value } => {
+                    data.push(port as u8 | 0x80);
+                    data.push(value);
+                }
+            }
+        }
+        
+        writer.write_all(&data)
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>10} {:>4}  {:<20}", self.cycle, self.program_counter, format_instruction(&self.instruction))?;
+        
+        for &(register, old, new) in &self.registers {
+            write!(f, " r{}:{:02x}->{:02x}", register, old, new)?;
+        }
+        
+        for &(address, old, new) in &self.memory {
+            write!(f, " mem[{}]:{:02x}->{:02x}", address, old, new)?;
+        }
+        
+        if let Some(zero_flag) = self.zero_flag {
+            write!(f, " Z={}", zero_flag as u8)?;
+        }
+        
+        if let Some(carry_flag) = self.carry_flag {
+            write!(f, " C={}", carry_flag as u8)?;
+        }
+        
+        for access in &self.ports {
+            match access {
+                PortAccess::Read { port, value } => write!(f, " in[{}]={:02x}", port, value)?,
+                PortAccess::Write { port, value } => write!(f, " out[{}]={:02x}", port, value)?
+            }
+        }
+        
+        if let Some(fault) = &self.fault {
+            write!(f, " fault: {}", fault)?;
+        }
+        
+        if let Some(source) = &self.source {
+            write!(f, "  ; {}", source)?;
+        }
+        
+        Ok(())
+    }
+}
+
+pub(crate) struct TraceStep<'a> {
+    pub cycle: u64,
+    pub program_counter: u32,
+    pub instruction: &'a Instruction,
+    pub source: Option<String>,
+    pub fault: Option<MachineError>,
+    pub registers: &'a [Word],
+    pub zero_flag: bool,
+    pub carry_flag: bool
+}
+
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    format: TraceFormat,
+    
+    header_written: bool,
+    error: Option<io::Error>,
+    
+    registers: [Word; REGISTER_COUNT],
+    zero_flag: bool,
+    carry_flag: bool,
+    memory: Vec<(usize, Word, Word)>,
+    ports: Vec<PortAccess>
+}
+
+impl Tracer {
+    pub fn new(writer: Box<dyn Write + Send>, format: TraceFormat) -> Self {
+        Self {
+            writer,
+            format,
+            
+            header_written: false,
+            error: None,
+            
+            registers: [0; REGISTER_COUNT],
+            zero_flag: false,
+            carry_flag: false,
+            memory: Vec::new(),
+            ports: Vec::new()
+        }
+    }
+    
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+    
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+    
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+    
+    pub fn into_writer(self) -> Box<dyn Write + Send> {
+        self.writer
+    }
+    
+    pub(crate) fn begin(&mut self, registers: &[Word], zero_flag: bool, carry_flag: bool) {
+        self.registers.copy_from_slice(registers);
+        self.zero_flag = zero_flag;
+        self.carry_flag = carry_flag;
+        self.memory.clear();
+        self.ports.clear();
+    }
+    
+    pub(crate) fn store(&mut self, address: usize, old: Word, new: Word) {
+        if old != new {
+            self.memory.push((address, old, new));
+        }
+    }
+    
+    pub(crate) fn port(&mut self, access: PortAccess) {
+        self.ports.push(access);
+    }
+    
+    pub(crate) fn end(&mut self, step: TraceStep) {
+        let entry = TraceEntry {
+            cycle: step.cycle,
+            program_counter: step.program_counter,
+            instruction: step.instruction.clone(),
+            source: step.source,
+            
+            registers: diff(&self.registers, step.registers),
+            memory: std::mem::take(&mut self.memory),
+            
+            zero_flag: (step.zero_flag != self.zero_flag).then_some(step.zero_flag),
+            carry_flag: (step.carry_flag != self.carry_flag).then_some(step.carry_flag),
+            
+            ports: std::mem::take(&mut self.ports),
+            fault: step.fault
+        };
+        
+        if self.error.is_some() {
+            return;
+        }
+        
+        if let Err(error) = self.write_entry(&entry) {
+            self.error = Some(error);
+        }
+    }
+    
+    fn write_entry(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{}", entry),
+            TraceFormat::Binary => {
+                if !self.header_written {
+                    self.writer.write_all(BINARY_MAGIC)?;
+                    self.writer.write_all(&[BINARY_VERSION])?;
+                    self.header_written = true;
+                }
+                
+                entry.write_binary(&mut self.writer)
+            }
+        }
+    }
+}
+
+fn diff(before: &[Word], after: &[Word]) -> Vec<(usize, Word, Word)> {
+    before.iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(index, (&old, &new))| (index, old, new))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Machine;
+    use batpu_assembly::components::immediate::Immediate;
+    use batpu_assembly::components::location::Location;
+    use batpu_assembly::components::offset::Offset;
+    use batpu_assembly::components::register::Register;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(format: TraceFormat) -> Vec<u8> {
+        let buffer = Buffer(Arc::new(Mutex::new(Vec::new())));
+
+        let mut machine = Machine::new();
+        machine.set_instructions(vec![
+            Instruction::NoOperation,
+            Instruction::LoadImmediate(Register::new(1), Immediate::new(3)),
+            Instruction::LoadImmediate(Register::new(2), Immediate::new(9)),
+            Instruction::MemoryStore(Register::new(1), Register::new(2), Offset::new(0)),
+            Instruction::Jump(Location::Label("missing".into()))
+        ]);
+
+        machine.tick().unwrap();
+        machine.set_tracer(Tracer::new(Box::new(buffer.clone()), format));
+
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        assert!(machine.tick().is_err());
+
+        buffer.0.lock().unwrap().clone()
+    }
+
+    struct Record {
+        cycle: u64,
+        program_counter: u16,
+        word: u16,
+        registers: Vec<[u8; 2]>,
+        memory: Vec<[u8; 2]>,
+        flags: u8,
+        ports: Vec<[u8; 2]>
+    }
+
+    fn records(mut data: &[u8]) -> Vec<Record> {
+        fn take<'a>(data: &mut &'a [u8], length: usize) -> &'a [u8] {
+            let (head, tail) = data.split_at(length);
+            *data = tail;
+            head
+        }
+
+        fn pairs(data: &mut &[u8]) -> Vec<[u8; 2]> {
+            let count = take(data, 1)[0] as usize;
+            take(data, count * 2).chunks(2).map(|pair| [pair[0], pair[1]]).collect()
+        }
+
+        let mut records = Vec::new();
+        while !data.is_empty() {
+            records.push(Record {
+                cycle: u64::from_le_bytes(take(&mut data, 8).try_into().unwrap()),
+                program_counter: u16::from_le_bytes(take(&mut data, 2).try_into().unwrap()),
+                word: u16::from_le_bytes(take(&mut data, 2).try_into().unwrap()),
+                registers: pairs(&mut data),
+                memory: pairs(&mut data),
+                flags: take(&mut data, 1)[0],
+                ports: pairs(&mut data)
+            });
+        }
+
+        records
+    }
+
+    #[test]
+    fn writes_text_records_with_deltas_and_faults() {
+        let text = String::from_utf8(trace(TraceFormat::Text)).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("         1    1"));
+        assert!(lines[0].contains("r1:00->03"));
+        assert!(lines[2].contains("mem[3]:00->09"));
+        assert!(!lines[2].contains("r1:"));
+        assert!(lines[3].contains("fault: Unresolved label"));
+    }
+
+    #[test]
+    fn writes_binary_records_with_the_machine_cycle_and_instruction_word() {
+        let data = trace(TraceFormat::Binary);
+
+        assert_eq!(&data[..4], BINARY_MAGIC);
+        assert_eq!(data[4], BINARY_VERSION);
+
+        let records = records(&data[5..]);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records.iter().map(|record| record.cycle).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        assert_eq!(records[0].program_counter, 1);
+        assert_eq!(records[0].word, 0x8103);
+        assert_eq!(records[0].registers, vec![[1, 3]]);
+        assert!(records[0].memory.is_empty());
+        assert_eq!(records[0].flags, 0);
+
+        assert_eq!(records[2].word, 0xF120);
+        assert!(records[2].registers.is_empty());
+        assert_eq!(records[2].memory, vec![[3, 9]]);
+        assert!(records[2].ports.is_empty());
+
+        assert_eq!(records[3].flags, FLAG_FAULT | FLAG_NO_WORD);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,282 @@
+use crate::components::controller::Controller;
+use crate::components::screen::Screen;
+use crate::machine::Machine;
+use ratatui::crossterm::event::KeyCode;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Start,
+    Select,
+    A,
+    B,
+    Up,
+    Right,
+    Down,
+    Left
+}
+
+pub const BUTTONS: [Button; 8] = [
+    Button::Start,
+    Button::Select,
+    Button::A,
+    Button::B,
+    Button::Up,
+    Button::Right,
+    Button::Down,
+    Button::Left
+];
+
+impl Button {
+    pub fn from_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::Enter => Some(Button::Start),
+            KeyCode::Tab | KeyCode::Backspace => Some(Button::Select),
+            KeyCode::Char('z') | KeyCode::Char('j') => Some(Button::A),
+            KeyCode::Char('x') | KeyCode::Char('k') => Some(Button::B),
+            KeyCode::Up | KeyCode::Char('w') => Some(Button::Up),
+            KeyCode::Right | KeyCode::Char('d') => Some(Button::Right),
+            KeyCode::Down | KeyCode::Char('s') => Some(Button::Down),
+            KeyCode::Left | KeyCode::Char('a') => Some(Button::Left),
+            _ => None
+        }
+    }
+
+    pub fn set(&self, controller: &mut Controller, pressed: bool) {
+        match self {
+            Button::Start => controller.start = pressed,
+            Button::Select => controller.select = pressed,
+            Button::A => controller.a = pressed,
+            Button::B => controller.b = pressed,
+            Button::Up => controller.up = pressed,
+            Button::Right => controller.right = pressed,
+            Button::Down => controller.down = pressed,
+            Button::Left => controller.left = pressed
+        }
+    }
+}
+
+pub struct Dashboard {
+    screen: String,
+    screen_width: u16,
+    screen_height: u16,
+
+    characters: String,
+
+    number: String,
+    number_signed: bool,
+
+    registers: String,
+    stack: String,
+    status: String
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self {
+            screen: String::new(),
+            screen_width: 0,
+            screen_height: 0,
+
+            characters: String::new(),
+
+            number: String::new(),
+            number_signed: false,
+
+            registers: String::new(),
+            stack: String::new(),
+            status: String::new()
+        }
+    }
+
+    pub fn update(&mut self, machine: &mut Machine) -> bool {
+        let mut changed = false;
+
+        if machine.screen().image_updated() {
+            self.screen = render_screen(machine.screen());
+            self.screen_width = machine.screen().width() as u16;
+            self.screen_height = machine.screen().height().div_ceil(2) as u16;
+
+            machine.screen_mut().disable_image_updated();
+            changed = true;
+        }
+
+        if machine.character_display().data_updated() {
+            self.characters = machine.character_display().data().to_string();
+
+            machine.character_display_mut().disable_data_updated();
+            changed = true;
+        }
+
+        let number_display = machine.number_display();
+        if number_display.value_updated() || number_display.signed != self.number_signed {
+            self.number = number_display.value().to_string();
+            self.number_signed = number_display.signed;
+
+            machine.number_display_mut().disable_value_updated();
+            changed = true;
+        }
+
+        if machine.registers_updated() || machine.flags_updated() {
+            self.registers = render_registers(machine);
+
+            machine.disable_registers_updated();
+            machine.disable_flags_updated();
+            changed = true;
+        }
+
+        if machine.stack().stack_updated() {
+            self.stack = machine.stack().stack()
+                .iter()
+                .rev()
+                .map(|address| address.to_string())
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            machine.stack_mut().disable_stack_updated();
+            changed = true;
+        }
+
+        changed
+    }
+
+    pub fn set_status(&mut self, status: String) -> bool {
+        if status == self.status {
+            return false;
+        }
+
+        self.status = status;
+        true
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [left, right] = Layout::horizontal([
+            Constraint::Length(self.screen_width + 2),
+            Constraint::Min(28)
+        ]).areas(frame.area());
+
+        let [screen, status] = Layout::vertical([
+            Constraint::Length(self.screen_height + 2),
+            Constraint::Min(3)
+        ]).areas(left);
+
+        let [characters, number, registers, stack] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Length(7),
+            Constraint::Min(3)
+        ]).areas(right);
+
+        frame.render_widget(Paragraph::new(self.screen.as_str()).block(Block::bordered().title("Screen")), screen);
+        frame.render_widget(Paragraph::new(self.status.as_str()).block(Block::bordered().title("Status")), status);
+
+        frame.render_widget(Paragraph::new(self.characters.as_str()).block(Block::bordered().title("Characters")), characters);
+        frame.render_widget(Paragraph::new(self.number.as_str()).block(Block::bordered().title("Number")), number);
+        frame.render_widget(Paragraph::new(self.registers.as_str()).block(Block::bordered().title("Registers")), registers);
+        frame.render_widget(Paragraph::new(self.stack.as_str()).block(Block::bordered().title("Stack")), stack);
+    }
+}
+
+pub fn render_screen(screen: &Screen) -> String {
+    let width = screen.width() as isize;
+    let height = screen.height() as isize;
+
+    let mut lines = Vec::with_capacity(screen.height().div_ceil(2));
+
+    let mut y = height - 1;
+    while y >= 0 {
+        let line: String = (0..width)
+            .map(|x| {
+                let top = screen.pixel(x, y);
+                let bottom = y > 0 && screen.pixel(x, y - 1);
+
+                match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' '
+                }
+            })
+            .collect();
+
+        lines.push(line);
+        y -= 2;
+    }
+
+    lines.join("\n")
+}
+
+fn render_registers(machine: &Machine) -> String {
+    let mut text = String::new();
+
+    for (register, value) in machine.registers().iter().enumerate() {
+        text.push_str(&format!("r{:<2}{:>4}", register, value));
+        text.push(if register % 4 == 3 { '\n' } else { ' ' });
+    }
+
+    text.push_str(&format!("zero {}  carry {}", machine.zero_flag() as u8, machine.carry_flag() as u8));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::config::MachineConfig;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn render(machine: &mut Machine) -> Vec<String> {
+        let mut dashboard = Dashboard::new();
+        assert!(dashboard.update(machine));
+        assert!(dashboard.set_status("Running".to_string()));
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn renders_the_machine() {
+        let mut machine = MachineConfig::new().screen_size(4, 4).build().unwrap();
+
+        let screen = machine.screen_mut();
+        for (x, y) in [(0, 3), (0, 2), (1, 3), (2, 0)] {
+            screen.x = x;
+            screen.y = y;
+            screen.set_pix(true);
+        }
+        screen.push_buffer();
+
+        let character_display = machine.character_display_mut();
+        for character in ['H', 'I'] {
+            character_display.push(Some(&character));
+        }
+        character_display.push_buffer();
+
+        machine.number_display_mut().signed = true;
+        machine.number_display_mut().set_value(0xFF);
+
+        machine.registers_mut()[5] = 42;
+        machine.registers_mut()[15] = 7;
+        machine.set_carry_flag(true);
+        machine.stack_mut().push(12).unwrap();
+
+        let lines = render(&mut machine);
+
+        assert!(lines[1].starts_with("│█▀  │"));
+        assert!(lines[2].starts_with("│  ▄ │"));
+        assert!(lines[1].contains("│HI"));
+        assert!(lines[4].contains("│-1"));
+        assert!(lines[5].starts_with("│Runn│"));
+        assert!(lines[8].contains("│r4    0 r5   42 r6    0 r7    0"));
+        assert!(lines[10].contains("│r12   0 r13   0 r14   0 r15   7"));
+        assert!(lines[11].contains("│zero 0  carry 1"));
+        assert!(lines[14].contains("│12"));
+    }
+}
\ No newline at end of file